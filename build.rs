@@ -0,0 +1,22 @@
+use std::{fs, io::Read, path::Path};
+
+const HTMX_PATH: &str = "static/script/htmx.min.js";
+const HTMX_SOURCE: &str = "https://cdn.jsdelivr.net/npm/htmx.org@2.0.6/dist/htmx.min.js";
+
+/// htmx isn't checked in, so fetch the pinned release on the first build.
+/// `--features embed` bakes `static/` into the binary and `serve` refuses to
+/// start without it, so a checkout that can't get it should fail here.
+fn main() {
+    println!("cargo:rerun-if-changed={HTMX_PATH}");
+    if Path::new(HTMX_PATH).exists() {
+        return;
+    }
+
+    let mut body = Vec::new();
+    ureq::get(HTMX_SOURCE).call()
+        .map_err(|e| e.to_string())
+        .and_then(|res| res.into_reader().read_to_end(&mut body).map_err(|e| e.to_string()))
+        .unwrap_or_else(|e| panic!("couldn't download htmx to {HTMX_PATH}: {e}"));
+    fs::write(HTMX_PATH, body)
+        .unwrap_or_else(|e| panic!("couldn't write {HTMX_PATH}: {e}"));
+}
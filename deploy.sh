@@ -3,16 +3,28 @@
 PI_HOST="pi"
 DIR="~/website"
 
-echo "[1/4] Building container..."
+echo "[1/5] Building container..."
 docker buildx build --platform linux/arm64 -t pi-site:latest --load .
 
-echo "[2/4] Uploading to Raspberry PI"
+echo "[2/5] Uploading to Raspberry PI"
 docker save pi-site:latest | ssh -C $PI_HOST "docker load"
 
-echo "[3/4] Syncinv .env."
+echo "[3/5] Syncinv .env."
 scp .env $PI_HOST:$DIR/.env
 
-echo "[4/4] Restarting container..."
+echo "[4/5] Restarting container..."
 ssh $PI_HOST "cd $DIR && docker compose up -d --force-recreate app"
 
+echo "[5/5] Waiting for container to become healthy..."
+for _ in $(seq 1 30); do
+    STATUS=$(ssh $PI_HOST "cd $DIR && docker inspect --format '{{.State.Health.Status}}' \$(docker compose ps -q app)")
+    [ "$STATUS" = "healthy" ] && break
+    sleep 2
+done
+
+if [ "$STATUS" != "healthy" ]; then
+    echo "[!/!] Container is $STATUS, check /readyz."
+    exit 1
+fi
+
 echo "[-/-] Finished."
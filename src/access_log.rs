@@ -0,0 +1,287 @@
+use std::{fs::{self, File, OpenOptions}, io::{self, Write}, net::IpAddr, path::PathBuf, sync::Mutex, time::Duration};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::util::LockExt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Plain,
+    Json,
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccessLogConfig {
+    /// Log to stdout when no path is set.
+    pub path: Option<PathBuf>,
+    pub format: LogFormat,
+    pub max_bytes: u64,
+    pub keep: usize,
+    pub anonymize_ip: bool,
+}
+
+impl Default for AccessLogConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            format: LogFormat::Plain,
+            max_bytes: 10 * 1024 * 1024,
+            keep: 5,
+            anonymize_ip: false,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct AccessEntry<'a> {
+    pub timestamp: DateTime<Utc>,
+    pub client_ip: Option<IpAddr>,
+    pub method: &'a str,
+    pub path: &'a str,
+    pub status: u16,
+    pub size: Option<usize>,
+    #[serde(rename = "latency_ms", serialize_with = "serialize_millis")]
+    pub latency: Duration,
+    pub user_agent: Option<&'a str>,
+    pub htmx: bool,
+    pub request_id: &'a str,
+}
+
+fn serialize_millis<S: serde::Serializer>(latency: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(latency.as_secs_f64() * 1000.0)
+}
+
+impl AccessEntry<'_> {
+    fn to_plain(&self) -> String {
+        let ip = self.client_ip.map_or("-".to_string(), |ip| ip.to_string());
+        let size = self.size.map_or("-".to_string(), |size| size.to_string());
+
+        format!(
+            "{} {} \"{} {}\" {} {} {:.1}ms \"{}\" htmx={} id={}",
+            self.timestamp.to_rfc3339_opts(SecondsFormat::Millis, true),
+            ip, self.method, self.path, self.status, size,
+            self.latency.as_secs_f64() * 1000.0,
+            self.user_agent.unwrap_or("-").replace('"', "\\\""),
+            self.htmx, self.request_id,
+        )
+    }
+}
+
+/// Zeroes the host part of an address: the last octet for IPv4 and the last
+/// 80 bits for IPv6.
+pub fn anonymize_ip(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, c, _] = v4.octets();
+            IpAddr::from([a, b, c, 0])
+        }
+        IpAddr::V6(v6) => {
+            let mut segments = v6.segments();
+            segments[3..].fill(0);
+            IpAddr::from(segments)
+        }
+    }
+}
+
+struct LogFile {
+    file: File,
+    size: u64,
+}
+
+pub struct AccessLog {
+    config: AccessLogConfig,
+    file: Mutex<Option<LogFile>>,
+}
+
+impl AccessLog {
+    pub fn new(config: AccessLogConfig) -> Result<Self, ()> {
+        let file = match &config.path {
+            Some(path) => Some(Self::open(path)
+                .map_err(|e| eprintln!("ERROR: Couldn't open access log `{}`: {e}", path.display()))?),
+            None => None,
+        };
+
+        Ok(Self { config, file: Mutex::new(file) })
+    }
+
+    fn open(path: &PathBuf) -> io::Result<LogFile> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(LogFile { file, size })
+    }
+
+    /// Shifts `access.log.N-1` to `access.log.N` down to `access.log` itself,
+    /// dropping whatever falls off the end, then starts a fresh file.
+    fn rotate(&self, path: &PathBuf) -> io::Result<LogFile> {
+        let rotated = |n: usize| PathBuf::from(format!("{}.{n}", path.display()));
+
+        if self.config.keep == 0 {
+            fs::remove_file(path)?;
+        } else {
+            for n in (1..self.config.keep).rev() {
+                if rotated(n).exists() { fs::rename(rotated(n), rotated(n + 1))?; }
+            }
+            fs::rename(path, rotated(1))?;
+        }
+
+        Self::open(path)
+    }
+
+    pub fn write(&self, mut entry: AccessEntry) {
+        if self.config.anonymize_ip {
+            entry.client_ip = entry.client_ip.map(anonymize_ip);
+        }
+
+        let line = match self.config.format {
+            LogFormat::Plain => entry.to_plain(),
+            LogFormat::Json => match serde_json::to_string(&entry) {
+                Ok(line) => line,
+                Err(e) => return eprintln!("ERROR: Couldn't serialize access log entry: {e}"),
+            },
+        };
+
+        let Some(path) = &self.config.path else {
+            return println!("{line}");
+        };
+
+        let mut guard = self.file.lock_recover();
+
+        // When rotating fails the entry still goes into the current file,
+        // and the next write tries again.
+        if let Some(log) = guard.as_mut().filter(|log| log.size >= self.config.max_bytes) {
+            match self.rotate(path) {
+                Ok(fresh) => *log = fresh,
+                Err(e) => eprintln!("ERROR: Couldn't rotate access log: {e}"),
+            }
+        }
+
+        if let Some(log) = guard.as_mut() {
+            match writeln!(log.file, "{line}") {
+                Ok(()) => log.size += line.len() as u64 + 1,
+                Err(e) => eprintln!("ERROR: Couldn't write access log: {e}"),
+            }
+        }
+    }
+}
+
+/// Reuses a well-formed `X-Request-Id` from upstream, otherwise generates one.
+pub fn request_id(incoming: Option<&str>) -> String {
+    if let Some(id) = incoming {
+        let is_valid = !id.is_empty() && id.len() <= 64
+            && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if is_valid { return id.to_string() }
+    }
+
+    let mut bytes = [0u8; 8];
+    getrandom::fill(&mut bytes).expect("OS random number generator unavailable");
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, net::IpAddr, path::Path, time::Duration};
+
+    use chrono::{TimeZone, Utc};
+
+    use super::{AccessEntry, AccessLog, AccessLogConfig, LogFormat, anonymize_ip, request_id};
+
+    fn entry(request_id: &str) -> AccessEntry<'_> {
+        AccessEntry {
+            timestamp: Utc.with_ymd_and_hms(2025, 3, 1, 12, 30, 0).unwrap(),
+            client_ip: Some(IpAddr::from([203, 0, 113, 7])),
+            method: "GET",
+            path: "/projects?tag=rust",
+            status: 200,
+            size: Some(512),
+            latency: Duration::from_micros(1500),
+            user_agent: Some("curl/8.0 \"quoted\""),
+            htmx: true,
+            request_id,
+        }
+    }
+
+    fn file_log(path: &Path, keep: usize) -> AccessLog {
+        AccessLog::new(AccessLogConfig {
+            path: Some(path.to_path_buf()),
+            format: LogFormat::Plain,
+            max_bytes: 1,
+            keep,
+            anonymize_ip: false,
+        }).unwrap()
+    }
+
+    #[test]
+    fn anonymizes_host_part() {
+        assert_eq!(anonymize_ip("203.0.113.7".parse().unwrap()), "203.0.113.0".parse::<IpAddr>().unwrap());
+        assert_eq!(
+            anonymize_ip("2001:db8:85a3:8d3:1319:8a2e:370:7348".parse().unwrap()),
+            "2001:db8:85a3::".parse::<IpAddr>().unwrap(),
+        );
+    }
+
+    #[test]
+    fn formats_plain_line() {
+        assert_eq!(
+            entry("abc").to_plain(),
+            "2025-03-01T12:30:00.000Z 203.0.113.7 \"GET /projects?tag=rust\" 200 512 1.5ms \"curl/8.0 \\\"quoted\\\"\" htmx=true id=abc",
+        );
+
+        let missing = AccessEntry { client_ip: None, size: None, user_agent: None, ..entry("abc") };
+        assert!(missing.to_plain().contains(" - \"GET /projects?tag=rust\" 200 - 1.5ms \"-\" "));
+    }
+
+    #[test]
+    fn keeps_valid_request_ids() {
+        assert_eq!(request_id(Some("req-123_ABC")), "req-123_ABC");
+
+        for invalid in [None, Some(""), Some("has space"), Some("new\nline"), Some(&*"a".repeat(65))] {
+            let id = request_id(invalid);
+            assert_eq!(id.len(), 16);
+            assert!(id.chars().all(|c| c.is_ascii_hexdigit()));
+        }
+    }
+
+    #[test]
+    fn rotates_and_drops_the_oldest() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let path = dir.join("access.log");
+        let log = file_log(&path, 2);
+
+        for id in ["first", "second", "third", "fourth"] {
+            log.write(entry(id));
+        }
+
+        let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+        assert!(read("access.log").contains("id=fourth"));
+        assert!(read("access.log.1").contains("id=third"));
+        assert!(read("access.log.2").contains("id=second"));
+        assert!(!dir.join("access.log.3").exists());
+    }
+
+    #[test]
+    fn keeps_writing_when_rotation_fails() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let path = dir.join("access.log");
+        let log = file_log(&path, 1);
+
+        // A directory in the way makes renaming the log fail.
+        fs::create_dir(dir.join("access.log.1")).unwrap();
+        for id in ["first", "second", "third"] {
+            log.write(entry(id));
+        }
+
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.contains("id=second") && content.contains("id=third"));
+
+        // Rotation picks up again once the way is clear.
+        fs::remove_dir(dir.join("access.log.1")).unwrap();
+        log.write(entry("fourth"));
+        assert!(fs::read_to_string(dir.join("access.log.1")).unwrap().contains("id=third"));
+        assert!(fs::read_to_string(&path).unwrap().contains("id=fourth"));
+    }
+}
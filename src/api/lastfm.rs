@@ -1,5 +1,7 @@
-use std::{time::Duration};
-use serde::{Deserialize, Deserializer, de::DeserializeOwned};
+use std::{collections::HashMap, fmt};
+use serde::{Deserialize, Deserializer, Serialize, de::DeserializeOwned};
+
+use crate::api::upstream::{Upstream, UpstreamConfig, UpstreamError};
 
 fn from_string_bool<'de, D>(deserializer: D) -> Result<bool, D::Error>
 where D: Deserializer<'de>
@@ -12,7 +14,6 @@ where D: Deserializer<'de>
 struct LfmImage {
     #[serde(rename="#text")]
      url: String,
-     size: String
 }
 
 #[derive(Deserialize)]
@@ -31,7 +32,6 @@ struct RecentTracks {
 #[derive(Deserialize)]
 struct LfmPageAttr {
      total: String,
-     user: String
 }
 
 #[derive(Deserialize)]
@@ -39,7 +39,6 @@ struct LfmRecentTrack {
     name: String,
     url: String,
     artist: LfmTextObj,
-    album: LfmTextObj,
     #[serde(rename="image")]
     images: Vec<LfmImage>,
     #[serde(rename="@attr")]
@@ -85,7 +84,6 @@ struct LfmTopAlbum {
 #[derive(Deserialize)]
 struct LfmSimpleArtist {
     name: String,
-    url: String
 }
 
 #[derive(Deserialize)]
@@ -134,20 +132,113 @@ struct LfmTopTrack {
 }
 
 #[derive(Deserialize)]
-struct UserInfoRoot {
-    user: LfmUser
+struct LfmError {
+    error: u32,
+    message: String,
 }
 
-#[derive(Deserialize)]
-struct LfmUser {
-    name: String,
-    playcount: String,
-    artist_couint: String
+// PUBLIC
+
+#[derive(Debug)]
+pub enum LastfmError {
+    /// Error 10 or 26, the API key is wrong or suspended.
+    InvalidApiKey(String),
+    /// Error 29, too many requests.
+    RateLimited,
+    /// Any other error Last.fm reported, like 6 for an unknown user.
+    Api { code: u32, message: String },
+    /// Network errors, error statuses without a Last.fm error in the body
+    /// and an open circuit.
+    Unavailable(String),
+    /// A body that doesn't look like the documented response.
+    InvalidResponse(String),
 }
 
-// PUBLIC
+impl LastfmError {
+    /// Reads Last.fm's `{"error": 10, "message": "..."}` error bodies.
+    fn parse(body: &str) -> Option<Self> {
+        let LfmError { error, message } = serde_json::from_str(body).ok()?;
+        Some(match error {
+            10 | 26 => Self::InvalidApiKey(message),
+            29 => Self::RateLimited,
+            code => Self::Api { code, message },
+        })
+    }
+
+    /// Errors that say Last.fm itself is struggling: 8 (operation failed),
+    /// 11 (service offline), 16 (temporarily unavailable) and rate limiting.
+    fn is_transient(&self) -> bool {
+        matches!(self, Self::RateLimited | Self::Api { code: 8 | 11 | 16, .. })
+    }
+}
+
+impl fmt::Display for LastfmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidApiKey(message) => write!(f, "invalid API key: {message}"),
+            Self::RateLimited => write!(f, "rate limited"),
+            Self::Api { code, message } => write!(f, "error {code}: {message}"),
+            Self::Unavailable(reason) => write!(f, "unavailable: {reason}"),
+            Self::InvalidResponse(e) => write!(f, "invalid response: {e}"),
+        }
+    }
+}
+
+/// Time range of the top artists, tracks and albums, as Last.fm names them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum Period {
+    #[serde(rename = "7day")]
+    Week,
+    #[default]
+    #[serde(rename = "1month")]
+    Month,
+    #[serde(rename = "3month")]
+    Quarter,
+    #[serde(rename = "6month")]
+    HalfYear,
+    #[serde(rename = "12month")]
+    Year,
+    #[serde(rename = "overall")]
+    Overall,
+}
+
+impl Period {
+    pub const ALL: [Period; 6] = [Self::Week, Self::Month, Self::Quarter, Self::HalfYear, Self::Year, Self::Overall];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Week     => "7day",
+            Self::Month    => "1month",
+            Self::Quarter  => "3month",
+            Self::HalfYear => "6month",
+            Self::Year     => "12month",
+            Self::Overall  => "overall",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Week     => "7 days",
+            Self::Month    => "1 month",
+            Self::Quarter  => "3 months",
+            Self::HalfYear => "6 months",
+            Self::Year     => "12 months",
+            Self::Overall  => "all time",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|period| period.as_str() == value)
+    }
+
+    /// The `period` query parameter, falling back to the default for
+    /// missing or unknown values.
+    pub fn from_params(params: &HashMap<String, String>) -> Self {
+        params.get("period").and_then(|value| Self::parse(value)).unwrap_or_default()
+    }
+}
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Track {
     pub name: String,
     pub artist: String,
@@ -157,7 +248,7 @@ pub struct Track {
     pub playcount: Option<u64>
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Album {
     pub name: String,
     pub artist: String,
@@ -166,7 +257,7 @@ pub struct Album {
     pub playcount: u64
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Artist {
     pub name: String,
     pub url: String,
@@ -174,7 +265,7 @@ pub struct Artist {
     pub playcount: u64
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserStats {
     pub total_scrobbles: u64,
     pub total_artists: u64,
@@ -185,106 +276,108 @@ pub struct UserStats {
 pub struct LastfmApi {
     api_key: String,
     username: String,
-    agent: ureq::Agent
+    upstream: Upstream,
 }
 
 impl LastfmApi {
-     pub fn new(api_key: String, username: String) -> Self {
-        let agent = ureq::AgentBuilder::new()
-            .timeout_read(Duration::from_secs(5))
-            .timeout_write(Duration::from_secs(5))
-            .build();
+     pub fn new(api_key: String, username: String, config: &UpstreamConfig) -> Self {
+        Self { api_key, username, upstream: Upstream::new("lastfm", config) }
+    }
 
-        Self { api_key, username, agent }
+    pub fn upstream(&self) -> &Upstream {
+        &self.upstream
     }
 
-    fn get<T: DeserializeOwned>(&self, method: &str, params: &str) -> Option<T> {
+    fn get<T: DeserializeOwned>(&self, method: &str, params: &str) -> Result<T, LastfmError> {
         let url = format!(
             "https://ws.audioscrobbler.com/2.0/?method={}&user={}&api_key={}&format=json&{}",
             method, self.username, self.api_key, params
         );
 
-        let resp = self.agent.get(&url).call().ok()?;
-        resp.into_json()
-            .map_err(|e| eprintln!("ERROR: LastFM Deserialization issue [{method}]: {e}"))
-            .ok()
-    } 
-
-    pub fn get_recent_tracks(&self, limit: usize) -> Vec<Track> {
-        let res: Option<RecentTracksRoot> = self.get("user.getRecentTracks", &format!("limit={limit}"));
-
-        match res {
-            Some(root) => root.recenttracks.tracks.into_iter().map(|t| Track {
-                name: t.name,
-                artist: t.artist.text,
-                url: t.url,
-                image_url: t.images.last().map(|i| i.url.clone()),
-                is_playing: t.attr.map(|a| a.now_playing).unwrap_or(false),
-                playcount: None
-            }).collect(),
-            None => Vec::new()
+        // Some errors come with a 200, so the body is checked either way.
+        // Transient ones count against the circuit like an error status.
+        let rejects = |body: &str| LastfmError::parse(body).is_some_and(|e| e.is_transient());
+
+        let result = match self.upstream.get_checked(&url, rejects) {
+            Ok(body) => match LastfmError::parse(&body) {
+                Some(error) => Err(error),
+                None => serde_json::from_str(&body).map_err(|e| LastfmError::InvalidResponse(e.to_string())),
+            },
+            Err(UpstreamError::Status(status, body)) => {
+                Err(LastfmError::parse(&body).unwrap_or(LastfmError::Unavailable(format!("status {status}"))))
+            }
+            Err(UpstreamError::Rejected(body)) => {
+                Err(LastfmError::parse(&body).unwrap_or(LastfmError::InvalidResponse(body)))
+            }
+            // Already logged when the circuit opened.
+            Err(UpstreamError::CircuitOpen) => return Err(LastfmError::Unavailable("circuit open".into())),
+            Err(e) => Err(LastfmError::Unavailable(e.to_string())),
+        };
+
+        if let Err(e) = &result {
+            eprintln!("ERROR: LastFM request failed [{method}]: {e}");
         }
+        result
     }
 
-    pub fn get_now_playing(&self) -> Option<Track> {
-        let tracks = self.get_recent_tracks(1);
-        let track = tracks.first()?;
-        if track.is_playing {
-            Some(track.clone()) 
-        } else {
-            None
-        } 
+    pub fn get_recent_tracks(&self, limit: usize) -> Result<Vec<Track>, LastfmError> {
+        let root: RecentTracksRoot = self.get("user.getRecentTracks", &format!("limit={limit}"))?;
+
+        Ok(root.recenttracks.tracks.into_iter().map(|t| Track {
+            name: t.name,
+            artist: t.artist.text,
+            url: t.url,
+            image_url: t.images.last().map(|i| i.url.clone()),
+            is_playing: t.attr.map(|a| a.now_playing).unwrap_or(false),
+            playcount: None
+        }).collect())
     }
 
-    pub fn get_top_albums(&self, limit: usize, period: &str) -> Vec<Album> {
-        let params = format!("limit={limit}&period={period}");
-        let res: Option<TopAlbumsRoot> = self.get("user.getTopAlbums", &params);
-
-        match res {
-            Some(root) => root.topalbums.albums.into_iter().map(|a| Album {
-                name: a.name,
-                artist: a.artist.name,
-                url: a.url,
-                image_url: a.images.last().map(|i| i.url.clone()),
-                playcount: a.playcount.parse().unwrap_or(0),
-            }).collect(),
-            None => Vec::new(),
-        }
+    pub fn get_now_playing(&self) -> Result<Option<Track>, LastfmError> {
+        let tracks = self.get_recent_tracks(1)?;
+        Ok(tracks.into_iter().next().filter(|track| track.is_playing))
     }
 
-    pub fn get_top_artists(&self, limit: usize, period: &str) -> Vec<Artist> {
-        let params = format!("limit={limit}&period={period}");
-        let res: Option<TopArtistsRoot> = self.get("user.getTopArtists", &params);
-
-        match res {
-            Some(root) => root.topartists.artists.into_iter().map(|a| Artist {
-                name: a.name,
-                url: a.url,
-                image_url: a.images.last().map(|i| i.url.clone()),
-                playcount: a.playcount.parse().unwrap_or(0),
-            }).collect(),
-            None => Vec::new(),
-        }
+    pub fn get_top_albums(&self, limit: usize, period: Period) -> Result<Vec<Album>, LastfmError> {
+        let params = format!("limit={limit}&period={}", period.as_str());
+        let root: TopAlbumsRoot = self.get("user.getTopAlbums", &params)?;
+
+        Ok(root.topalbums.albums.into_iter().map(|a| Album {
+            name: a.name,
+            artist: a.artist.name,
+            url: a.url,
+            image_url: a.images.last().map(|i| i.url.clone()),
+            playcount: a.playcount.parse().unwrap_or(0),
+        }).collect())
     }
 
-    pub fn get_top_tracks(&self, limit: usize, period: &str) -> Vec<Track> {
-        let params = format!("limit={limit}&period={period}");
-        let res: Option<TopTracksRoot> = self.get("user.getTopTracks", &params);
-
-        match res {
-            Some(root) => root.toptracks.tracks.into_iter().map(|t| Track {
-                name: t.name,
-                artist: t.artist.name,
-                url: t.url,
-                image_url: t.images.last().map(|i| i.url.clone()),
-                is_playing: false,
-                playcount: Some(t.playcount.parse().unwrap_or(0)),
-            }).collect(),
-            None => Vec::new(),
-        }
+    pub fn get_top_artists(&self, limit: usize, period: Period) -> Result<Vec<Artist>, LastfmError> {
+        let params = format!("limit={limit}&period={}", period.as_str());
+        let root: TopArtistsRoot = self.get("user.getTopArtists", &params)?;
+
+        Ok(root.topartists.artists.into_iter().map(|a| Artist {
+            name: a.name,
+            url: a.url,
+            image_url: a.images.last().map(|i| i.url.clone()),
+            playcount: a.playcount.parse().unwrap_or(0),
+        }).collect())
     }
 
-    pub fn get_user_stats(&self) -> Option<UserStats> {
+    pub fn get_top_tracks(&self, limit: usize, period: Period) -> Result<Vec<Track>, LastfmError> {
+        let params = format!("limit={limit}&period={}", period.as_str());
+        let root: TopTracksRoot = self.get("user.getTopTracks", &params)?;
+
+        Ok(root.toptracks.tracks.into_iter().map(|t| Track {
+            name: t.name,
+            artist: t.artist.name,
+            url: t.url,
+            image_url: t.images.last().map(|i| i.url.clone()),
+            is_playing: false,
+            playcount: Some(t.playcount.parse().unwrap_or(0)),
+        }).collect())
+    }
+
+    pub fn get_user_stats(&self) -> Result<UserStats, LastfmError> {
         let recent: RecentTracksRoot = self.get("user.getRecenttracks", "limit=1")?;
         let total_scrobbles = recent.recenttracks.attr.total.parse().unwrap_or(0);
 
@@ -297,7 +390,7 @@ impl LastfmApi {
         let tracks: TopTracksRoot = self.get("user.getTopTracks", "limit=1")?;
         let total_tracks = tracks.toptracks.attr.total.parse().unwrap_or(0);
 
-        Some(UserStats {
+        Ok(UserStats {
             total_scrobbles,
             total_artists,
             total_albums,
@@ -305,3 +398,47 @@ impl LastfmApi {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::LastfmError;
+
+    #[test]
+    fn parses_error_bodies() {
+        for code in [10, 26] {
+            let body = format!(r#"{{"error": {code}, "message": "Invalid API key"}}"#);
+            assert!(matches!(LastfmError::parse(&body), Some(LastfmError::InvalidApiKey(message)) if message == "Invalid API key"));
+        }
+        assert!(matches!(
+            LastfmError::parse(r#"{"error": 29, "message": "Rate limit exceeded"}"#),
+            Some(LastfmError::RateLimited),
+        ));
+        assert!(matches!(
+            LastfmError::parse(r#"{"message": "User not found", "error": 6, "links": []}"#),
+            Some(LastfmError::Api { code: 6, message }) if message == "User not found",
+        ));
+    }
+
+    #[test]
+    fn ignores_other_bodies() {
+        for body in [
+            r#"{"recenttracks": {"track": [], "@attr": {"total": "0"}}}"#,
+            r#"{"error": "not a number", "message": "x"}"#,
+            r#"{"error": 6}"#,
+            "<html>Bad Gateway</html>",
+            "",
+        ] {
+            assert!(LastfmError::parse(body).is_none(), "`{body}` isn't an error body");
+        }
+    }
+
+    #[test]
+    fn only_service_errors_are_transient() {
+        let transient = |body: &str| LastfmError::parse(body).unwrap().is_transient();
+        assert!(transient(r#"{"error": 29, "message": ""}"#));
+        assert!(transient(r#"{"error": 11, "message": "Service Offline"}"#));
+        assert!(transient(r#"{"error": 16, "message": ""}"#));
+        assert!(!transient(r#"{"error": 10, "message": ""}"#));
+        assert!(!transient(r#"{"error": 6, "message": ""}"#));
+    }
+}
@@ -1,2 +1,3 @@
 pub mod lastfm;
+pub mod upstream;
 pub mod wttr;
@@ -0,0 +1,343 @@
+use std::{fmt, sync::Mutex, thread, time::{Duration, Instant}};
+
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::{metrics::UpstreamStats, util::LockExt};
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct UpstreamConfig {
+    /// Connect, read and write timeout of a single attempt.
+    pub timeout_secs: u64,
+    /// Extra attempts after a failed one, for network errors, 429 and 5xx.
+    pub retries: u32,
+    /// Upper bound of the first backoff, doubled for every retry. The actual
+    /// wait is random between zero and that bound.
+    pub backoff_ms: u64,
+    /// Failed calls in a row that open the circuit.
+    pub failure_threshold: u32,
+    /// How long an open circuit fails calls without trying upstream.
+    pub cooldown_secs: u64,
+}
+
+impl Default for UpstreamConfig {
+    fn default() -> Self {
+        Self {
+            timeout_secs: 5,
+            retries: 2,
+            backoff_ms: 250,
+            failure_threshold: 5,
+            cooldown_secs: 30,
+        }
+    }
+}
+
+pub enum UpstreamError {
+    /// Too many recent failures, upstream wasn't called.
+    CircuitOpen,
+    /// Upstream answered with an error status, the body is kept because
+    /// APIs explain the error there.
+    Status(u16, String),
+    Transport(String),
+    /// Upstream answered with a success status, but the body says it failed.
+    Rejected(String),
+}
+
+impl fmt::Display for UpstreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CircuitOpen => write!(f, "circuit open"),
+            Self::Status(code, body) => {
+                let excerpt: String = body.chars().take(200).collect();
+                write!(f, "status {code}: {}", excerpt.trim())
+            }
+            Self::Transport(e) => write!(f, "{e}"),
+            Self::Rejected(body) => {
+                let excerpt: String = body.chars().take(200).collect();
+                write!(f, "error in response: {}", excerpt.trim())
+            }
+        }
+    }
+}
+
+impl UpstreamError {
+    /// Worth another attempt, and a sign upstream is in trouble.
+    fn is_transient(&self) -> bool {
+        match self {
+            Self::CircuitOpen => false,
+            Self::Status(code, _) => *code == 429 || *code >= 500,
+            Self::Transport(_) | Self::Rejected(_) => true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum CircuitState {
+    Closed,
+    Open { until: Instant },
+    /// The cooldown is over and one call is finding out whether upstream
+    /// is back. Everything else still fails fast. Should that call never
+    /// finish, another one gets to try after `until`.
+    HalfOpen { until: Instant },
+}
+
+struct Circuit {
+    state: CircuitState,
+    failures: u32,
+}
+
+/// HTTP client for an upstream API, shared by its callers.
+pub struct Upstream {
+    name: &'static str,
+    agent: ureq::Agent,
+    config: UpstreamConfig,
+    circuit: Mutex<Circuit>,
+    stats: UpstreamStats,
+}
+
+impl Upstream {
+    pub fn new(name: &'static str, config: &UpstreamConfig) -> Self {
+        let timeout = Duration::from_secs(config.timeout_secs);
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(timeout)
+            .timeout_read(timeout)
+            .timeout_write(timeout)
+            .build();
+
+        Self {
+            name,
+            agent,
+            config: config.clone(),
+            circuit: Mutex::new(Circuit { state: CircuitState::Closed, failures: 0 }),
+            stats: UpstreamStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> &UpstreamStats {
+        &self.stats
+    }
+
+    /// Fetches `url` and returns the body. Transient errors are retried,
+    /// and after enough failed calls in a row the circuit opens and calls
+    /// fail right away until the cooldown is over.
+    pub fn get(&self, url: &str) -> Result<String, UpstreamError> {
+        self.get_checked(url, |_| false)
+    }
+
+    /// Like `get`, for APIs that report some failures with a success status.
+    /// Bodies `rejects` returns true for count as failed attempts.
+    pub fn get_checked(&self, url: &str, rejects: impl Fn(&str) -> bool) -> Result<String, UpstreamError> {
+        self.enter()?;
+
+        let mut attempt = 0;
+        let result = loop {
+            let started = Instant::now();
+            let result = self.attempt(url)
+                .and_then(|body| if rejects(&body) { Err(UpstreamError::Rejected(body)) } else { Ok(body) });
+            self.stats.record(started.elapsed(), result.is_ok());
+
+            match result {
+                Err(e) if e.is_transient() && attempt < self.config.retries => {
+                    thread::sleep(self.backoff(attempt));
+                    attempt += 1;
+                }
+                result => break result,
+            }
+        };
+
+        self.leave(&result);
+        result
+    }
+
+    fn attempt(&self, url: &str) -> Result<String, UpstreamError> {
+        match self.agent.get(url).call() {
+            Ok(response) => response.into_string()
+                .map_err(|e| UpstreamError::Transport(e.to_string())),
+            Err(ureq::Error::Status(code, response)) => {
+                Err(UpstreamError::Status(code, response.into_string().unwrap_or_default()))
+            }
+            // Not `e.to_string()`, that includes the URL and with it any
+            // API key.
+            Err(ureq::Error::Transport(e)) => Err(UpstreamError::Transport(match e.message() {
+                Some(message) => format!("{}: {message}", e.kind()),
+                None => e.kind().to_string(),
+            })),
+        }
+    }
+
+    /// Full jitter: anywhere between zero and the doubled bound, so callers
+    /// that failed together don't retry together.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let bound = self.config.backoff_ms.saturating_mul(1 << attempt.min(16));
+        let mut bytes = [0u8; 8];
+        getrandom::fill(&mut bytes).expect("OS random number generator unavailable");
+        Duration::from_millis(u64::from_le_bytes(bytes) % bound.saturating_add(1))
+    }
+
+    fn cooldown(&self) -> Duration {
+        Duration::from_secs(self.config.cooldown_secs)
+    }
+
+    fn enter(&self) -> Result<(), UpstreamError> {
+        let mut circuit = self.circuit.lock_recover();
+        let now = Instant::now();
+        match circuit.state {
+            CircuitState::Closed => Ok(()),
+            CircuitState::Open { until } | CircuitState::HalfOpen { until } if now >= until => {
+                circuit.state = CircuitState::HalfOpen { until: now + self.cooldown() };
+                Ok(())
+            }
+            CircuitState::Open { .. } | CircuitState::HalfOpen { .. } => Err(UpstreamError::CircuitOpen),
+        }
+    }
+
+    fn leave(&self, result: &Result<String, UpstreamError>) {
+        let mut circuit = self.circuit.lock_recover();
+        let failed = result.as_ref().is_err_and(UpstreamError::is_transient);
+
+        if !failed {
+            if circuit.state != CircuitState::Closed {
+                println!("Upstream {} is back, closing circuit", self.name);
+            }
+            *circuit = Circuit { state: CircuitState::Closed, failures: 0 };
+            return;
+        }
+
+        circuit.failures += 1;
+        let is_trial = matches!(circuit.state, CircuitState::HalfOpen { .. });
+        if is_trial || circuit.failures >= self.config.failure_threshold {
+            if circuit.state == CircuitState::Closed {
+                eprintln!("ERROR: Upstream {} failed {} times in a row, opening circuit for {}s", self.name, circuit.failures, self.config.cooldown_secs);
+            }
+            circuit.state = CircuitState::Open { until: Instant::now() + self.cooldown() };
+        }
+    }
+
+    /// Circuit state for the health endpoint.
+    pub fn health(&self) -> Value {
+        let circuit = self.circuit.lock_recover();
+        match circuit.state {
+            CircuitState::Closed => json!({ "circuit": "closed", "consecutive_failures": circuit.failures }),
+            CircuitState::HalfOpen { .. } => json!({ "circuit": "half_open", "consecutive_failures": circuit.failures }),
+            CircuitState::Open { until } => json!({
+                "circuit": "open",
+                "consecutive_failures": circuit.failures,
+                "retry_in_seconds": until.saturating_duration_since(Instant::now()).as_secs(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::{Duration, Instant}};
+
+    use tiny_http::{Response, Server};
+
+    use crate::util::LockExt;
+
+    use super::{CircuitState, Upstream, UpstreamConfig, UpstreamError};
+
+    fn upstream() -> Upstream {
+        Upstream::new("test", &UpstreamConfig { failure_threshold: 3, cooldown_secs: 60, ..UpstreamConfig::default() })
+    }
+
+    fn fail(upstream: &Upstream) {
+        upstream.leave(&Err(UpstreamError::Transport("connection refused".into())));
+    }
+
+    /// Skips the rest of the cooldown.
+    fn cool_down(upstream: &Upstream) {
+        upstream.circuit.lock_recover().state = CircuitState::Open { until: Instant::now() };
+    }
+
+    #[test]
+    fn opens_after_threshold_failures_in_a_row() {
+        let upstream = upstream();
+        fail(&upstream);
+        fail(&upstream);
+        upstream.leave(&Ok(String::new()));
+        fail(&upstream);
+        fail(&upstream);
+        assert!(upstream.enter().is_ok());
+
+        fail(&upstream);
+        assert!(matches!(upstream.enter(), Err(UpstreamError::CircuitOpen)));
+        assert_eq!(upstream.health()["circuit"], "open");
+    }
+
+    #[test]
+    fn backoff_stays_within_its_bound() {
+        let upstream = upstream();
+        for attempt in [0, 1, 20] {
+            assert!(upstream.backoff(attempt) <= Duration::from_millis(250 << attempt.min(16)));
+        }
+
+        let huge = Upstream::new("test", &UpstreamConfig { backoff_ms: u64::MAX, ..UpstreamConfig::default() });
+        huge.backoff(3);
+    }
+
+    #[test]
+    fn half_open_allows_a_single_trial() {
+        let upstream = upstream();
+        for _ in 0..3 { fail(&upstream) }
+        cool_down(&upstream);
+
+        assert!(upstream.enter().is_ok());
+        assert_eq!(upstream.health()["circuit"], "half_open");
+        assert!(matches!(upstream.enter(), Err(UpstreamError::CircuitOpen)));
+
+        // A failed trial opens the circuit again right away.
+        fail(&upstream);
+        assert!(matches!(upstream.enter(), Err(UpstreamError::CircuitOpen)));
+        assert_eq!(upstream.health()["circuit"], "open");
+    }
+
+    #[test]
+    fn successful_trial_closes_the_circuit() {
+        let upstream = upstream();
+        for _ in 0..3 { fail(&upstream) }
+        cool_down(&upstream);
+
+        assert!(upstream.enter().is_ok());
+        upstream.leave(&Ok(String::new()));
+        assert_eq!(upstream.health()["circuit"], "closed");
+        assert_eq!(upstream.health()["consecutive_failures"], 0);
+        assert!(upstream.enter().is_ok() && upstream.enter().is_ok());
+    }
+
+    #[test]
+    fn rejected_bodies_count_as_failures() {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", server.server_addr().to_ip().unwrap());
+        thread::spawn(move || {
+            for request in server.incoming_requests() {
+                let _ = request.respond(Response::from_string("{\"error\": 29}"));
+            }
+        });
+
+        let upstream = Upstream::new("test", &UpstreamConfig { retries: 0, failure_threshold: 2, ..UpstreamConfig::default() });
+        assert!(upstream.get(&url).is_ok());
+
+        let rejects = |body: &str| body.contains("error");
+        assert!(matches!(upstream.get_checked(&url, rejects), Err(UpstreamError::Rejected(_))));
+        assert!(matches!(upstream.get_checked(&url, rejects), Err(UpstreamError::Rejected(_))));
+        assert!(matches!(upstream.get(&url), Err(UpstreamError::CircuitOpen)));
+    }
+
+    #[test]
+    fn client_errors_dont_count_as_failures() {
+        let upstream = upstream();
+        for _ in 0..5 {
+            upstream.leave(&Err(UpstreamError::Status(404, "not found".into())));
+        }
+        assert_eq!(upstream.health()["consecutive_failures"], 0);
+        assert!(upstream.enter().is_ok());
+
+        for _ in 0..3 {
+            upstream.leave(&Err(UpstreamError::Status(429, "slow down".into())));
+        }
+        assert!(matches!(upstream.enter(), Err(UpstreamError::CircuitOpen)));
+    }
+}
@@ -1,22 +1,32 @@
-const WTTR_URL: &str = "http://wttr.in/Eindhoven?format=2";
+use percent_encoding::{NON_ALPHANUMERIC, utf8_percent_encode};
+
+use crate::api::upstream::{Upstream, UpstreamConfig, UpstreamError};
 
 pub struct WttrApi {
-    agent: ureq::Agent,
+    url: String,
+    upstream: Upstream,
 }
 
 impl WttrApi {
-    pub fn new() -> Self {
-        let agent = ureq::AgentBuilder::new()
-            .build();
+    pub fn new(location: &str, config: &UpstreamConfig) -> Self {
+        let url = format!("http://wttr.in/{}?format=2", utf8_percent_encode(location, NON_ALPHANUMERIC));
+
+        Self { url, upstream: Upstream::new("wttr", config) }
+    }
 
-        Self { agent }
+    pub fn upstream(&self) -> &Upstream {
+        &self.upstream
     }
-    pub fn get_weather(&self) -> String {
-        match self.agent.get(WTTR_URL).call() {
-            Ok(res) => {
-                res.into_string().unwrap_or("Couldn't get weather".to_string())
-            },
-            Err(_) => "Wttr Timeout".to_string()
+
+    /// The current weather as a line of text. Failures are left to the
+    /// caller, so they don't end up cached as if they were the weather.
+    pub fn get_weather(&self) -> Result<String, UpstreamError> {
+        let result = self.upstream.get(&self.url);
+        match &result {
+            // Already logged when the circuit opened.
+            Err(UpstreamError::CircuitOpen) | Ok(_) => {}
+            Err(e) => eprintln!("ERROR: wttr.in request failed: {e}"),
         }
+        result
     }
 }
@@ -0,0 +1,306 @@
+use std::{fs::{self, File}, path::{Path, PathBuf}, sync::OnceLock, time::{SystemTime, UNIX_EPOCH}};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use percent_encoding::percent_decode_str;
+use sha2::{Digest, Sha384};
+
+static STATIC_ROOT: OnceLock<PathBuf> = OnceLock::new();
+
+/// Sets the directory static files are served from. Only the first call has
+/// an effect; until then `./static` is used.
+pub fn set_root(path: PathBuf) {
+    let _ = STATIC_ROOT.set(path);
+}
+
+pub fn root() -> &'static Path {
+    STATIC_ROOT.get_or_init(|| PathBuf::from("./static"))
+}
+
+pub enum AssetData {
+    File(File),
+    #[cfg_attr(not(feature = "embed"), allow(dead_code))]
+    Embedded(&'static [u8]),
+}
+
+pub struct Asset {
+    pub data: AssetData,
+    pub content_type: &'static str,
+    pub etag: String,
+}
+
+pub fn content_type_for(path: &Path) -> &'static str {
+    let ext = path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase());
+
+    match ext.as_deref() {
+        Some("html") | Some("htm") => "text/html; charset=utf-8",
+        Some("css")  => "text/css; charset=utf-8",
+        Some("js") | Some("mjs") => "application/javascript; charset=utf-8",
+        Some("json") => "application/json",
+        Some("xml")  => "application/xml",
+        Some("txt")  => "text/plain; charset=utf-8",
+        Some("toml") => "application/toml",
+        Some("wasm") => "application/wasm",
+        Some("png")  => "image/png",
+        Some("gif")  => "image/gif",
+        Some("svg")  => "image/svg+xml",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("webp") => "image/webp",
+        Some("avif") => "image/avif",
+        Some("ico")  => "image/x-icon",
+        Some("ttf")  => "font/ttf",
+        Some("otf")  => "font/otf",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        _            => "application/octet-stream"
+    }
+}
+
+/// Percent-decodes a request path relative to the static root and rejects
+/// anything that could step outside of it. The result only contains plain
+/// `/`-separated components.
+pub fn decode_path(rel_path: &str) -> Option<String> {
+    let decoded = percent_decode_str(rel_path).decode_utf8().ok()?;
+
+    if decoded.contains('\\') || decoded.contains('\0') || decoded.starts_with('/') {
+        return None
+    }
+
+    let mut components = Vec::new();
+    for component in decoded.split('/') {
+        match component {
+            "" | "." => continue,
+            ".." => return None,
+            c => components.push(c),
+        }
+    }
+
+    if components.is_empty() { return None }
+    Some(components.join("/"))
+}
+
+/// Resolves a request path to a file on disk, making sure the canonical path,
+/// with all symlinks followed, still lies inside `root`.
+pub fn resolve_path(root: &Path, rel_path: &str) -> Option<PathBuf> {
+    let decoded = decode_path(rel_path)?;
+
+    let root = root.canonicalize().ok()?;
+    let path = root.join(decoded).canonicalize().ok()?;
+
+    if !path.starts_with(&root) || !path.is_file() { return None }
+    Some(path)
+}
+
+fn load_from_disk(rel_path: &str) -> Option<Asset> {
+    let path = resolve_path(root(), rel_path)?;
+
+    let file = File::open(&path)
+        .map_err(|e| eprintln!("ERROR: Couldn't open file `{}`: {e}", path.display()))
+        .ok()?;
+
+    let metadata = file.metadata().ok()?;
+    let modified = metadata.modified().ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+
+    Some(Asset {
+        data: AssetData::File(file),
+        content_type: content_type_for(&path),
+        etag: format!("\"{:x}-{:x}\"", metadata.len(), modified),
+    })
+}
+
+/// Reads a file from the static directory on disk, falling back to the
+/// embedded copy when the binary was built with the `embed` feature.
+pub fn load(rel_path: &str) -> Option<Asset> {
+    load_from_disk(rel_path).or_else(|| embedded::load(&decode_path(rel_path)?))
+}
+
+pub fn read_bytes(rel_path: &str) -> Option<Vec<u8>> {
+    if let Some(path) = resolve_path(root(), rel_path) {
+        return fs::read(&path)
+            .map_err(|e| eprintln!("ERROR: Couldn't read file `{}`: {e}", path.display()))
+            .ok();
+    }
+
+    embedded::read_bytes(&decode_path(rel_path)?).map(<[u8]>::to_vec)
+}
+
+/// Modification time of the file on disk, `None` for embedded or missing
+/// files.
+pub fn modified(rel_path: &str) -> Option<SystemTime> {
+    let path = resolve_path(root(), rel_path)?;
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+pub fn read_text(rel_path: &str) -> Option<String> {
+    String::from_utf8(read_bytes(rel_path)?)
+        .map_err(|e| eprintln!("ERROR: File `{rel_path}` is not valid UTF-8: {e}"))
+        .ok()
+}
+
+/// Subresource Integrity value (`sha384-...`) for a static file.
+pub fn integrity(rel_path: &str) -> Option<String> {
+    let digest = Sha384::digest(read_bytes(rel_path)?);
+    Some(format!("sha384-{}", STANDARD.encode(digest)))
+}
+
+#[cfg(feature = "embed")]
+mod embedded {
+    use std::{collections::HashMap, path::Path, sync::OnceLock};
+
+    use include_dir::{Dir, include_dir};
+
+    use super::{Asset, AssetData, content_type_for};
+
+    static STATIC_DIR: Dir<'static> = include_dir!("$CARGO_MANIFEST_DIR/static");
+
+    /// FNV-1a, only used to derive stable ETags for the embedded files.
+    fn hash_bytes(data: &[u8]) -> u64 {
+        data.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        })
+    }
+
+    struct EmbeddedAsset {
+        data: &'static [u8],
+        content_type: &'static str,
+        etag: String,
+    }
+
+    fn collect(dir: &'static Dir<'static>, index: &mut HashMap<&'static Path, EmbeddedAsset>) {
+        for file in dir.files() {
+            index.insert(file.path(), EmbeddedAsset {
+                data: file.contents(),
+                content_type: content_type_for(file.path()),
+                etag: format!("\"{:016x}\"", hash_bytes(file.contents())),
+            });
+        }
+        for sub in dir.dirs() { collect(sub, index) }
+    }
+
+    fn index() -> &'static HashMap<&'static Path, EmbeddedAsset> {
+        static INDEX: OnceLock<HashMap<&'static Path, EmbeddedAsset>> = OnceLock::new();
+        INDEX.get_or_init(|| {
+            let mut index = HashMap::new();
+            collect(&STATIC_DIR, &mut index);
+            index
+        })
+    }
+
+    pub fn count() -> usize {
+        index().len()
+    }
+
+    pub fn load(rel_path: &str) -> Option<Asset> {
+        index().get(Path::new(rel_path)).map(|asset| Asset {
+            data: AssetData::Embedded(asset.data),
+            content_type: asset.content_type,
+            etag: asset.etag.clone(),
+        })
+    }
+
+    pub fn read_bytes(rel_path: &str) -> Option<&'static [u8]> {
+        index().get(Path::new(rel_path)).map(|asset| asset.data)
+    }
+}
+
+#[cfg(not(feature = "embed"))]
+mod embedded {
+    use super::Asset;
+
+    pub fn count() -> usize { 0 }
+    pub fn load(_rel_path: &str) -> Option<Asset> { None }
+    pub fn read_bytes(_rel_path: &str) -> Option<&'static [u8]> { None }
+}
+
+pub fn embedded_count() -> usize {
+    embedded::count()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::*;
+
+    fn static_root() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("static/img")).unwrap();
+        fs::write(dir.path().join("static/img/a b.png"), b"png").unwrap();
+        fs::write(dir.path().join("static/welcome.txt"), b"hi").unwrap();
+        fs::write(dir.path().join("secret.txt"), b"secret").unwrap();
+        dir
+    }
+
+    #[test]
+    fn content_types_cover_served_files() {
+        assert_eq!(content_type_for(Path::new("img/favicon.ico")), "image/x-icon");
+        assert_eq!(content_type_for(Path::new("font/IBMPlexMono-Bold.ttf")), "font/ttf");
+        assert_eq!(content_type_for(Path::new("welcome.txt")), "text/plain; charset=utf-8");
+        assert_eq!(content_type_for(Path::new("img/photo.WEBP")), "image/webp");
+        assert_eq!(content_type_for(Path::new("img/photo.avif")), "image/avif");
+        assert_eq!(content_type_for(Path::new("feed.xml")), "application/xml");
+        assert_eq!(content_type_for(Path::new("data.json")), "application/json");
+        assert_eq!(content_type_for(Path::new("app.wasm")), "application/wasm");
+        assert_eq!(content_type_for(Path::new("font/x.woff2")), "font/woff2");
+        assert_eq!(content_type_for(Path::new("no_extension")), "application/octet-stream");
+    }
+
+    #[test]
+    fn decode_path_rejects_traversal() {
+        assert_eq!(decode_path("img/a%20b.png").as_deref(), Some("img/a b.png"));
+        assert_eq!(decode_path("./img//x.png").as_deref(), Some("img/x.png"));
+
+        for path in [
+            "../secret.txt",
+            "img/../../secret.txt",
+            "%2e%2e/secret.txt",
+            "%2E%2E%2Fsecret.txt",
+            "img/%2e%2e%2f%2e%2e%2fsecret.txt",
+            "..%5csecret.txt",
+            "/etc/passwd",
+            "%2fetc%2fpasswd",
+            "img/x.png%00.txt",
+            "%ff%fe",
+            "",
+            "./",
+        ] {
+            assert_eq!(decode_path(path), None, "{path:?} should be rejected");
+        }
+    }
+
+    #[test]
+    fn resolve_path_stays_inside_root() {
+        let dir = static_root();
+        let root = dir.path().join("static");
+
+        assert!(resolve_path(&root, "img/a%20b.png").is_some());
+        assert!(resolve_path(&root, "welcome.txt").is_some());
+
+        assert!(resolve_path(&root, "img").is_none());
+        assert!(resolve_path(&root, "missing.txt").is_none());
+        assert!(resolve_path(&root, "../secret.txt").is_none());
+        assert!(resolve_path(&root, "%2e%2e/secret.txt").is_none());
+        assert!(resolve_path(&root, "img/%2e%2e/%2e%2e/secret.txt").is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolve_path_refuses_symlinks_outside_root() {
+        use std::os::unix::fs::symlink;
+
+        let dir = static_root();
+        let root = dir.path().join("static");
+
+        symlink(dir.path().join("secret.txt"), root.join("leak.txt")).unwrap();
+        symlink(dir.path(), root.join("parent")).unwrap();
+        symlink(root.join("welcome.txt"), root.join("alias.txt")).unwrap();
+
+        assert!(resolve_path(&root, "leak.txt").is_none());
+        assert!(resolve_path(&root, "parent/secret.txt").is_none());
+        assert!(resolve_path(&root, "alias.txt").is_some());
+    }
+}
@@ -0,0 +1,206 @@
+use std::{collections::BTreeSet, fs, io::ErrorKind, path::{Path, PathBuf}, sync::Arc, time::SystemTime};
+
+use chrono::NaiveDate;
+use serde::Deserialize;
+use toml::value::Datetime;
+
+use crate::{config::BlogConfig, markdown::{self, Document}, util::{reload::Reloadable, slugify}};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FrontMatter {
+    title: String,
+    date: Datetime,
+    updated: Option<Datetime>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    draft: bool,
+    summary: Option<String>,
+    slug: Option<String>,
+}
+
+pub struct Post {
+    pub slug: String,
+    pub title: String,
+    pub date: NaiveDate,
+    pub updated: Option<NaiveDate>,
+    pub tags: Vec<String>,
+    pub draft: bool,
+    pub summary: Option<String>,
+    pub document: Document,
+}
+
+pub fn toml_date(datetime: &Datetime) -> Option<NaiveDate> {
+    let date = datetime.date?;
+    NaiveDate::from_ymd_opt(date.year.into(), date.month.into(), date.day.into())
+}
+
+/// Splits `+++`-delimited TOML front matter from the Markdown body.
+fn split_front_matter(source: &str) -> Option<(&str, &str)> {
+    let rest = source.strip_prefix("+++")?.trim_start_matches(['\r', '\n']);
+    let end = rest.find("\n+++")?;
+    let body = rest[end + 4..].trim_start_matches(['\r', '\n']);
+    // With CRLF line endings the `\r` before the closing `+++` is left over.
+    Some((rest[..end].trim_end_matches('\r'), body))
+}
+
+fn parse_post(path: &Path, source: &str) -> Result<Post, ()> {
+    let (front, body) = split_front_matter(source)
+        .ok_or_else(|| eprintln!("ERROR: `{}` doesn't start with `+++` front matter", path.display()))?;
+    let front: FrontMatter = toml::from_str(front)
+        .map_err(|e| eprintln!("ERROR: Couldn't parse front matter of `{}`: {e}", path.display()))?;
+
+    let date = toml_date(&front.date)
+        .ok_or_else(|| eprintln!("ERROR: `{}` needs a `date` like 2025-01-31", path.display()))?;
+    let updated = match &front.updated {
+        Some(updated) => Some(toml_date(updated)
+            .ok_or_else(|| eprintln!("ERROR: `{}` has an invalid `updated` date", path.display()))?),
+        None => None,
+    };
+
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+    let slug = front.slug.unwrap_or_else(|| slugify(stem));
+    if slug.is_empty() {
+        eprintln!("ERROR: `{}` needs a `slug`", path.display());
+        return Err(());
+    }
+
+    Ok(Post {
+        slug,
+        title: front.title,
+        date,
+        updated,
+        tags: front.tags,
+        draft: front.draft,
+        summary: front.summary,
+        document: markdown::render(body),
+    })
+}
+
+fn markdown_files(dir: &Path) -> Result<Vec<PathBuf>, ()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => {
+            eprintln!("ERROR: Couldn't read posts directory `{}`: {e}", dir.display());
+            return Err(());
+        }
+    };
+
+    Ok(entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "md") && path.is_file())
+        .collect())
+}
+
+/// Newest modification time of the directory and its posts. Adding or
+/// removing a post touches the directory, editing one touches the file.
+fn newest_change(dir: &Path) -> Option<SystemTime> {
+    let dir_modified = fs::metadata(dir).and_then(|meta| meta.modified()).ok()?;
+    let files = markdown_files(dir).ok()?;
+
+    files.iter()
+        .filter_map(|path| fs::metadata(path).and_then(|meta| meta.modified()).ok())
+        .chain([dir_modified])
+        .max()
+}
+
+/// Every post in `dir`, newest first. Drafts are left out unless
+/// `include_drafts` is set.
+fn load_posts(dir: &Path, include_drafts: bool) -> Result<Vec<Post>, ()> {
+    let mut posts = Vec::new();
+    for path in markdown_files(dir)? {
+        let source = fs::read_to_string(&path)
+            .map_err(|e| eprintln!("ERROR: Couldn't read post `{}`: {e}", path.display()))?;
+        posts.push(parse_post(&path, &source)?);
+    }
+
+    for (i, post) in posts.iter().enumerate() {
+        if posts[..i].iter().any(|other| other.slug == post.slug) {
+            eprintln!("ERROR: More than one post has the slug `{}`", post.slug);
+            return Err(());
+        }
+    }
+
+    posts.retain(|post| include_drafts || !post.draft);
+    posts.sort_by(|a, b| b.date.cmp(&a.date).then_with(|| a.title.cmp(&b.title)));
+    Ok(posts)
+}
+
+pub struct Blog {
+    posts: Reloadable<Vec<Post>>,
+}
+
+impl Blog {
+    pub fn new(config: &BlogConfig) -> Result<Self, ()> {
+        let dir = config.posts_dir.clone();
+        let include_drafts = config.show_drafts;
+
+        let modified_dir = dir.clone();
+        let posts = Reloadable::with_loader(
+            dir.display().to_string(),
+            Box::new(move || newest_change(&modified_dir)),
+            Box::new(move || load_posts(&dir, include_drafts)),
+        )?;
+
+        Ok(Self { posts })
+    }
+
+    /// The posts, newest first. Drafts are only included with `show_drafts`.
+    pub fn posts(&self) -> Arc<Vec<Post>> {
+        self.posts.get()
+    }
+
+    /// Every tag used by one of `posts`.
+    pub fn tags(posts: &[Post]) -> BTreeSet<&str> {
+        posts.iter().flat_map(|post| post.tags.iter().map(String::as_str)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{parse_post, split_front_matter};
+
+    const POST: &str = "+++\ntitle = \"Hello\"\ndate = 2025-01-31\ntags = [\"rust\"]\n+++\n\n# Hi\n";
+
+    #[test]
+    fn splits_front_matter() {
+        assert_eq!(split_front_matter(POST), Some(("title = \"Hello\"\ndate = 2025-01-31\ntags = [\"rust\"]", "# Hi\n")));
+        assert_eq!(split_front_matter("# No front matter\n"), None);
+        assert_eq!(split_front_matter("+++\ntitle = \"Unclosed\"\n"), None);
+    }
+
+    #[test]
+    fn parses_crlf_front_matter() {
+        let post = parse_post(Path::new("crlf.md"), &POST.replace('\n', "\r\n")).unwrap();
+        assert_eq!(post.title, "Hello");
+        assert_eq!(post.tags, ["rust"]);
+        assert_eq!(post.document.toc[0].id, "hi");
+    }
+
+    #[test]
+    fn falls_back_to_the_file_name_for_the_slug() {
+        let post = parse_post(Path::new("posts/My First Post.md"), POST).unwrap();
+        assert_eq!(post.slug, "my-first-post");
+        assert_eq!(post.date.to_string(), "2025-01-31");
+        assert!(!post.draft);
+
+        let custom = POST.replace("date =", "slug = \"custom\"\ndate =");
+        assert_eq!(parse_post(Path::new("posts/ignored.md"), &custom).unwrap().slug, "custom");
+
+        assert!(parse_post(Path::new("posts/!!!.md"), POST).is_err());
+    }
+
+    #[test]
+    fn rejects_missing_or_invalid_front_matter() {
+        let path = Path::new("broken.md");
+        assert!(parse_post(path, "# Just markdown\n").is_err());
+        assert!(parse_post(path, "+++\ntitle = \"No date\"\n+++\n").is_err());
+        assert!(parse_post(path, "+++\ntitle = \"Bad date\"\ndate = \"yesterday\"\n+++\n").is_err());
+        assert!(parse_post(path, "+++\ntitle = \"Unknown\"\ndate = 2025-01-31\nauthor = \"me\"\n+++\n").is_err());
+        assert!(parse_post(path, "+++\ntitle = \"Time only\"\ndate = 12:00:00\n+++\n").is_err());
+    }
+}
@@ -0,0 +1,157 @@
+use std::{fs, io::ErrorKind, path::{Path, PathBuf}, sync::Mutex, thread, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{api::lastfm::{Album, Artist, Period, Track, UserStats}, state::{LastfmCache, WttrCache}, util::{LockExt, keyed_cache::SavedEntry}};
+
+/// Bumped when the layout changes, older snapshots are ignored.
+const VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    version: u32,
+    now_playing: Vec<SavedEntry<(), Option<Track>>>,
+    top_artists: Vec<SavedEntry<Period, Vec<Artist>>>,
+    top_tracks: Vec<SavedEntry<Period, Vec<Track>>>,
+    top_albums: Vec<SavedEntry<Period, Vec<Album>>>,
+    user_stats: Vec<SavedEntry<(), UserStats>>,
+    weather: Vec<SavedEntry<(), String>>,
+}
+
+/// Writes of the periodic thread and the one on shutdown mustn't interleave.
+static WRITING: Mutex<()> = Mutex::new(());
+
+/// Writes the cached upstream data to `path`, through a temporary file so
+/// a crash halfway leaves the previous snapshot intact.
+pub fn save(path: &Path, lastfm: &LastfmCache, wttr: &WttrCache) -> Result<(), ()> {
+    let snapshot = Snapshot {
+        version: VERSION,
+        now_playing: lastfm.now_playing.snapshot(),
+        top_artists: lastfm.top_artists.snapshot(),
+        top_tracks: lastfm.top_tracks.snapshot(),
+        top_albums: lastfm.top_albums.snapshot(),
+        user_stats: lastfm.user_stats.snapshot(),
+        weather: wttr.weather.snapshot(),
+    };
+    let json = serde_json::to_vec(&snapshot)
+        .map_err(|e| eprintln!("ERROR: Couldn't serialize cache snapshot: {e}"))?;
+
+    let _writing = WRITING.lock_recover();
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+
+    fs::write(&tmp_path, json)
+        .map_err(|e| eprintln!("ERROR: Couldn't write cache snapshot `{}`: {e}", tmp_path.display()))?;
+    fs::rename(&tmp_path, path)
+        .map_err(|e| eprintln!("ERROR: Couldn't replace cache snapshot `{}`: {e}", path.display()))
+}
+
+/// Fills the caches from the snapshot at `path`. A missing or unreadable
+/// snapshot only means starting with empty caches.
+pub fn load(path: &Path, lastfm: &LastfmCache, wttr: &WttrCache) {
+    let content = match fs::read(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => return,
+        Err(e) => return eprintln!("ERROR: Couldn't read cache snapshot `{}`: {e}", path.display()),
+    };
+
+    let snapshot: Snapshot = match serde_json::from_slice(&content) {
+        Ok(snapshot) => snapshot,
+        Err(e) => return eprintln!("ERROR: Ignoring invalid cache snapshot `{}`: {e}", path.display()),
+    };
+    if snapshot.version != VERSION {
+        return eprintln!("ERROR: Ignoring cache snapshot `{}` from version {}", path.display(), snapshot.version);
+    }
+
+    let restored = snapshot.now_playing.len() + snapshot.top_artists.len() + snapshot.top_tracks.len()
+        + snapshot.top_albums.len() + snapshot.user_stats.len() + snapshot.weather.len();
+
+    lastfm.now_playing.restore(snapshot.now_playing);
+    lastfm.top_artists.restore(snapshot.top_artists);
+    lastfm.top_tracks.restore(snapshot.top_tracks);
+    lastfm.top_albums.restore(snapshot.top_albums);
+    lastfm.user_stats.restore(snapshot.user_stats);
+    wttr.weather.restore(snapshot.weather);
+
+    println!("Restored {restored} cache entries from `{}`", path.display());
+}
+
+/// Saves a snapshot every `interval`, so a crash loses little. The thread
+/// only holds the caches and just ends with the process.
+pub fn spawn_periodic(path: PathBuf, interval: Duration, lastfm: LastfmCache, wttr: WttrCache) {
+    let spawned = thread::Builder::new()
+        .name("cache-snapshot".into())
+        .spawn(move || loop {
+            thread::sleep(interval);
+            let _ = save(&path, &lastfm, &wttr);
+        });
+
+    if let Err(e) = spawned {
+        eprintln!("ERROR: Couldn't start periodic cache snapshots: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::{api::lastfm::Period, config::CacheConfig, state::{LastfmCache, WttrCache}};
+
+    use super::{load, save};
+
+    fn caches() -> (LastfmCache, WttrCache) {
+        let config = CacheConfig::default();
+        (LastfmCache::new(&config), WttrCache::new(&config))
+    }
+
+    fn cached_weather(wttr: &WttrCache) -> Option<String> {
+        wttr.weather.get_or_update(|| None).map(|weather| weather.to_string())
+    }
+
+    #[test]
+    fn restores_saved_entries_into_fresh_caches() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache-snapshot.json");
+
+        let (lastfm, wttr) = caches();
+        wttr.weather.get_or_update(|| Some("Sunny +20°C".to_string()));
+        lastfm.top_artists.get_or_update(Period::Month, || Some(Vec::new()));
+        save(&path, &lastfm, &wttr).unwrap();
+        assert!(!dir.path().join("cache-snapshot.json.tmp").exists());
+
+        let (lastfm, wttr) = caches();
+        load(&path, &lastfm, &wttr);
+        assert_eq!(cached_weather(&wttr).as_deref(), Some("Sunny +20°C"));
+        assert!(lastfm.top_artists.get_or_update(Period::Month, || None).is_some());
+        assert!(lastfm.top_artists.get_or_update(Period::Year, || None).is_none());
+    }
+
+    #[test]
+    fn ignores_a_missing_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let (lastfm, wttr) = caches();
+
+        load(&dir.path().join("missing.json"), &lastfm, &wttr);
+        assert_eq!(cached_weather(&wttr), None);
+    }
+
+    #[test]
+    fn ignores_other_versions_and_invalid_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache-snapshot.json");
+
+        let (lastfm, wttr) = caches();
+        wttr.weather.get_or_update(|| Some("Rain".to_string()));
+        save(&path, &lastfm, &wttr).unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+        assert!(saved.starts_with("{\"version\":1,"));
+
+        for content in [saved.replace("\"version\":1", "\"version\":999"), "{\"version\":1}".into(), "not json".into()] {
+            fs::write(&path, content).unwrap();
+            let (lastfm, wttr) = caches();
+            load(&path, &lastfm, &wttr);
+            assert_eq!(cached_weather(&wttr), None);
+        }
+    }
+}
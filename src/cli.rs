@@ -0,0 +1,298 @@
+use std::path::PathBuf;
+
+const USAGE: &str = "\
+Usage: personal-website [COMMAND] [OPTIONS]
+
+Commands:
+  serve          Start the web server (default)
+  check-config   Validate the configuration and projects.toml, then exit
+  db             Guestbook database maintenance
+  version        Print the version
+  help           Print this help, or the help of a command
+
+Run `personal-website help <COMMAND>` for the options of a command.
+
+Exit codes:
+  0  success
+  1  runtime error
+  2  invalid command line
+  3  invalid configuration";
+
+const SERVE_USAGE: &str = "\
+Usage: personal-website serve [OPTIONS]
+
+Options:
+  --config <FILE>     Configuration file [default: config.toml]
+  --bind <ADDR>       Overrides server.address
+  --static-dir <DIR>  Overrides server.static_dir
+  --db <FILE>         Overrides server.db_path
+  -h, --help          Print this help";
+
+const CHECK_CONFIG_USAGE: &str = "\
+Usage: personal-website check-config [OPTIONS]
+
+Loads the configuration the same way `serve` does, including environment
+overrides, and checks that projects.toml and the home page text files load.
+
+Options:
+  --config <FILE>     Configuration file [default: config.toml]
+  --static-dir <DIR>  Overrides server.static_dir
+  -h, --help          Print this help";
+
+const DB_USAGE: &str = "\
+Usage: personal-website db <ACTION> [OPTIONS]
+
+Actions:
+  check                Run SQLite's integrity check
+  vacuum               Rebuild the database file to reclaim space
+  backup <FILE>        Write a consistent copy of the database to FILE
+  delete-message <ID>  Remove a guestbook message
+
+Options:
+  --config <FILE>     Configuration file [default: config.toml]
+  --db <FILE>         Overrides server.db_path
+  -h, --help          Print this help";
+
+const VERSION_USAGE: &str = "\
+Usage: personal-website version";
+
+/// Values given on the command line, applied on top of the config file and
+/// the environment.
+#[derive(Default)]
+pub struct Overrides {
+    pub config: Option<PathBuf>,
+    pub bind: Option<String>,
+    pub static_dir: Option<PathBuf>,
+    pub db: Option<PathBuf>,
+}
+
+pub enum DbAction {
+    Check,
+    Vacuum,
+    Backup(PathBuf),
+    DeleteMessage(i64),
+}
+
+pub enum Command {
+    Serve(Overrides),
+    CheckConfig(Overrides),
+    Db(DbAction, Overrides),
+    Version,
+    Help(&'static str),
+}
+
+fn command_usage(name: &str) -> Option<&'static str> {
+    match name {
+        "serve"        => Some(SERVE_USAGE),
+        "check-config" => Some(CHECK_CONFIG_USAGE),
+        "db"           => Some(DB_USAGE),
+        "version"      => Some(VERSION_USAGE),
+        "help"         => Some(USAGE),
+        _ => None,
+    }
+}
+
+/// Parses `--flag <value>` options. `allowed` lists the flags the command
+/// accepts, anything else that starts with a dash is an error.
+fn parse_options(
+    args: &mut impl Iterator<Item = String>,
+    allowed: &[&str],
+    positional: &mut Vec<String>,
+) -> Result<Overrides, String> {
+    let mut overrides = Overrides::default();
+
+    while let Some(arg) = args.next() {
+        if !arg.starts_with('-') {
+            positional.push(arg);
+            continue;
+        }
+
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+            None => (arg, None),
+        };
+        if !allowed.contains(&flag.as_str()) {
+            return Err(format!("unexpected option `{flag}`"));
+        }
+
+        let value = inline.or_else(|| args.next())
+            .filter(|value| !value.is_empty())
+            .ok_or_else(|| format!("`{flag}` needs a value"))?;
+
+        match flag.as_str() {
+            "--config"     => overrides.config = Some(value.into()),
+            "--bind"       => overrides.bind = Some(value),
+            "--static-dir" => overrides.static_dir = Some(value.into()),
+            "--db"         => overrides.db = Some(value.into()),
+            _ => unreachable!("`{flag}` is allowed but not handled"),
+        }
+    }
+
+    Ok(overrides)
+}
+
+/// Whether `-h` or `--help` is given as a flag, rather than as the value of
+/// one like in `--config -h`.
+fn wants_help(args: &[String]) -> bool {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return true,
+            flag if flag.starts_with("--") && !flag.contains('=') => { args.next(); }
+            _ => {}
+        }
+    }
+    false
+}
+
+fn expect_no_positional(command: &str, positional: &[String]) -> Result<(), String> {
+    match positional.first() {
+        Some(arg) => Err(format!("unexpected argument `{arg}` for `{command}`")),
+        None => Ok(()),
+    }
+}
+
+fn parse_db_action(mut positional: Vec<String>) -> Result<DbAction, String> {
+    if positional.is_empty() {
+        return Err("`db` needs an action".into());
+    }
+    let action = positional.remove(0);
+    let mut operands = positional.into_iter();
+
+    let parsed = match action.as_str() {
+        "check"  => DbAction::Check,
+        "vacuum" => DbAction::Vacuum,
+        "backup" => {
+            let file = operands.next().ok_or("`db backup` needs a target file")?;
+            DbAction::Backup(file.into())
+        }
+        "delete-message" => {
+            let id = operands.next().ok_or("`db delete-message` needs a message id")?;
+            let id = id.parse().map_err(|_| format!("`{id}` is not a valid message id"))?;
+            DbAction::DeleteMessage(id)
+        }
+        _ => return Err(format!("unknown db action `{action}`")),
+    };
+
+    match operands.next() {
+        Some(arg) => Err(format!("unexpected argument `{arg}` for `db {action}`")),
+        None => Ok(parsed),
+    }
+}
+
+/// Parses the arguments after the program name. Errors are meant to be
+/// printed together with `USAGE`.
+pub fn parse(args: Vec<String>) -> Result<Command, String> {
+    let mut args = args.into_iter().peekable();
+
+    // Options without a command still mean `serve`, so `--bind` works on
+    // its own.
+    let command = match args.peek().map(String::as_str) {
+        None => return Ok(Command::Serve(Overrides::default())),
+        Some("-h" | "--help" | "-V" | "--version") => args.next().unwrap_or_default(),
+        Some(arg) if arg.starts_with('-') => "serve".to_string(),
+        Some(_) => args.next().unwrap_or_default(),
+    };
+
+    let rest: Vec<String> = args.collect();
+    if wants_help(&rest) {
+        if let Some(usage) = command_usage(&command) {
+            return Ok(Command::Help(usage));
+        }
+    }
+
+    let mut rest = rest.into_iter();
+    let mut positional = Vec::new();
+
+    match command.as_str() {
+        "-h" | "--help" => Ok(Command::Help(USAGE)),
+        "help" => match rest.next() {
+            None => Ok(Command::Help(USAGE)),
+            Some(topic) => command_usage(&topic).map(Command::Help)
+                .ok_or_else(|| format!("unknown command `{topic}`")),
+        },
+        "-V" | "--version" | "version" => {
+            expect_no_positional("version", rest.as_slice())?;
+            Ok(Command::Version)
+        }
+        "serve" => {
+            let overrides = parse_options(&mut rest, &["--config", "--bind", "--static-dir", "--db"], &mut positional)?;
+            expect_no_positional("serve", &positional)?;
+            Ok(Command::Serve(overrides))
+        }
+        "check-config" => {
+            let overrides = parse_options(&mut rest, &["--config", "--static-dir"], &mut positional)?;
+            expect_no_positional("check-config", &positional)?;
+            Ok(Command::CheckConfig(overrides))
+        }
+        "db" => {
+            let overrides = parse_options(&mut rest, &["--config", "--db"], &mut positional)?;
+            Ok(Command::Db(parse_db_action(positional)?, overrides))
+        }
+        _ => Err(format!("unknown command `{command}`")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{Command, DB_USAGE, DbAction, SERVE_USAGE, USAGE, parse};
+
+    fn run(args: &str) -> Result<Command, String> {
+        parse(args.split_whitespace().map(String::from).collect())
+    }
+
+    fn help(args: &str) -> &'static str {
+        match run(args) {
+            Ok(Command::Help(usage)) => usage,
+            _ => panic!("`{args}` should print help"),
+        }
+    }
+
+    #[test]
+    fn serves_by_default() {
+        let Ok(Command::Serve(overrides)) = run("") else { panic!("expected serve") };
+        assert!(overrides.config.is_none() && overrides.bind.is_none());
+
+        let Ok(Command::Serve(overrides)) = run("--bind 127.0.0.1:8080 --db=site.db") else { panic!("expected serve") };
+        assert_eq!(overrides.bind.as_deref(), Some("127.0.0.1:8080"));
+        assert_eq!(overrides.db, Some(PathBuf::from("site.db")));
+    }
+
+    #[test]
+    fn parses_db_actions() {
+        let Ok(Command::Db(DbAction::Backup(file), overrides)) = run("db backup out.db --config prod.toml") else {
+            panic!("expected db backup")
+        };
+        assert_eq!(file, PathBuf::from("out.db"));
+        assert_eq!(overrides.config, Some(PathBuf::from("prod.toml")));
+
+        assert!(matches!(run("db delete-message 42"), Ok(Command::Db(DbAction::DeleteMessage(42), _))));
+        assert!(matches!(run("db check"), Ok(Command::Db(DbAction::Check, _))));
+    }
+
+    #[test]
+    fn rejects_invalid_arguments() {
+        for args in [
+            "unknown", "serve extra", "serve --verbose", "serve --bind", "serve --bind=",
+            "check-config --bind 0.0.0.0:80", "db", "db shrink", "db backup", "db delete-message abc",
+            "db check extra", "version extra", "help unknown",
+        ] {
+            assert!(run(args).is_err(), "`{args}` should be rejected");
+        }
+    }
+
+    #[test]
+    fn prints_help_for_flags_only() {
+        assert_eq!(help("-h"), USAGE);
+        assert_eq!(help("help"), USAGE);
+        assert_eq!(help("help db"), DB_USAGE);
+        assert_eq!(help("serve --bind 0.0.0.0:80 -h"), SERVE_USAGE);
+        assert_eq!(help("--help"), USAGE);
+        assert_eq!(help("db backup --help"), DB_USAGE);
+
+        let Ok(Command::Serve(overrides)) = run("serve --config -h") else { panic!("expected serve") };
+        assert_eq!(overrides.config, Some(PathBuf::from("-h")));
+    }
+}
@@ -0,0 +1,590 @@
+use std::{env, ffi::OsString, fs, io::ErrorKind, net::{IpAddr, ToSocketAddrs}, path::{Path, PathBuf}};
+
+use serde::Deserialize;
+use toml::{Table, Value};
+use url::Url;
+
+use crate::{access_log::AccessLogConfig, api::upstream::UpstreamConfig, security::SecurityHeaders};
+
+pub const DEFAULT_PATH: &str = "config.toml";
+
+/// Upper limit for cache durations, far beyond any useful value but small
+/// enough that adding them to a point in time can't overflow.
+const MAX_CACHE_SECS: u64 = 365 * 24 * 60 * 60;
+
+/// Upper limit for the first retry backoff. It doubles with every retry, so
+/// anything longer keeps a request waiting far past any client's patience.
+const MAX_BACKOFF_MS: u64 = 60 * 1000;
+
+/// Prefix for environment overrides. Nested keys are separated by a double
+/// underscore, so `SITE_SERVER__THREADS=8` sets `server.threads`. Variables
+/// without one, like `SITE_URL`, aren't overrides and are left alone.
+const ENV_PREFIX: &str = "SITE_";
+
+/// Older environment variables that keep working, mapped to their key.
+const ENV_ALIASES: [(&str, &str); 2] = [
+    ("SERVER_ADDRESS", "server.address"),
+    ("LASTFM_KEY", "lastfm.api_key"),
+];
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub site: SiteConfig,
+    pub lastfm: LastfmConfig,
+    pub weather: WeatherConfig,
+    pub upstream: UpstreamConfig,
+    pub rate_limit: RateLimitConfig,
+    pub cache: CacheConfig,
+    pub blog: BlogConfig,
+    pub security: SecurityHeaders,
+    pub access_log: AccessLogConfig,
+    pub metrics: MetricsConfig,
+    pub ui: UiConfig,
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub address: String,
+    pub threads: usize,
+    pub queue_capacity: usize,
+    pub retry_after_secs: u64,
+    /// Stays below Docker's default 10 second grace period before SIGKILL.
+    pub shutdown_timeout_secs: u64,
+    pub static_dir: PathBuf,
+    pub db_path: PathBuf,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            address: "0.0.0.0:3000".into(),
+            threads: 16,
+            queue_capacity: 64,
+            retry_after_secs: 5,
+            shutdown_timeout_secs: 8,
+            static_dir: "./static".into(),
+            db_path: "guestbook.db".into(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SiteConfig {
+    /// Public address of the site, used for absolute links in the sitemap
+    /// and feed.
+    pub base_url: String,
+    pub title: String,
+    pub author: String,
+    /// Default description for pages that don't have their own.
+    pub description: String,
+    /// Link preview image for pages that don't have their own.
+    pub image: String,
+    /// Generates a preview image with the page title for pages without an
+    /// image of their own. Needs the `og-image` feature.
+    pub og_images: bool,
+}
+
+impl Default for SiteConfig {
+    fn default() -> Self {
+        Self {
+            base_url: "https://mentaalachtergesteld.nl".into(),
+            title: "MentaalAchtergesteld".into(),
+            author: "MentaalAchtergesteld".into(),
+            description: "Personal website of MentaalAchtergesteld, with projects, a blog and a guestbook.".into(),
+            image: "/static/img/personal-website.png".into(),
+            og_images: false,
+        }
+    }
+}
+
+impl SiteConfig {
+    /// `path` on the public site, which should start with a `/`.
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{path}", self.base_url.trim_end_matches('/'))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LastfmConfig {
+    pub username: String,
+    pub api_key: Option<String>,
+}
+
+impl Default for LastfmConfig {
+    fn default() -> Self {
+        Self { username: "gravitowl".into(), api_key: None }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WeatherConfig {
+    pub location: String,
+}
+
+impl Default for WeatherConfig {
+    fn default() -> Self {
+        Self { location: "Eindhoven".into() }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub cooldown_secs: u64,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self { cooldown_secs: 10 }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    pub now_playing_secs: u64,
+    pub top_artists_secs: u64,
+    pub top_tracks_secs: u64,
+    pub top_albums_secs: u64,
+    pub user_stats_secs: u64,
+    pub weather_secs: u64,
+    /// How long past expiry a value is still served while it refreshes in
+    /// the background. After that, visitors wait for the upstream call.
+    pub max_stale_secs: u64,
+    /// Refreshes values this long before they expire. 0 turns it off.
+    pub refresh_ahead_secs: u64,
+    /// Cached upstream data is saved here on shutdown and loaded on start.
+    /// Defaults to `cache-snapshot.json` next to `server.db_path`, see
+    /// `Config::snapshot_path`.
+    pub snapshot_path: Option<PathBuf>,
+    /// Also saves the snapshot this often. 0 only saves on shutdown.
+    pub snapshot_interval_secs: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            now_playing_secs: 60,
+            top_artists_secs: 60 * 60 * 60,
+            top_tracks_secs: 60 * 60,
+            top_albums_secs: 60 * 60,
+            user_stats_secs: 5 * 60,
+            weather_secs: 15 * 60,
+            max_stale_secs: 24 * 60 * 60,
+            refresh_ahead_secs: 0,
+            snapshot_path: None,
+            snapshot_interval_secs: 5 * 60,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BlogConfig {
+    pub posts_dir: PathBuf,
+    /// Lists drafts like published posts, for previewing locally.
+    pub show_drafts: bool,
+}
+
+impl Default for BlogConfig {
+    fn default() -> Self {
+        Self { posts_dir: "./content/posts".into(), show_drafts: false }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    /// Addresses allowed to scrape `/metrics` directly.
+    pub allow: Vec<IpAddr>,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self { allow: vec![IpAddr::from([127, 0, 0, 1]), IpAddr::from([0, 0, 0, 0, 0, 0, 0, 1])] }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct NavItem {
+    pub href: String,
+    pub label: String,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SocialLink {
+    pub url: String,
+    pub icon: String,
+    pub alt: String,
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UiConfig {
+    pub navbar: Vec<NavItem>,
+    pub socials: Vec<SocialLink>,
+}
+
+impl Default for UiConfig {
+    fn default() -> Self {
+        let nav = |href: &str, label: &str| NavItem { href: href.into(), label: label.into() };
+        let social = |url: &str, icon: &str, alt: &str| SocialLink { url: url.into(), icon: icon.into(), alt: alt.into() };
+
+        Self {
+            navbar: vec![
+                nav("/home",      "Home"),
+                nav("/guestbook", "Guestbook"),
+                nav("/projects",  "Projects"),
+                nav("/blog",      "Blog"),
+                nav("/interests", "Interests"),
+            ],
+            socials: vec![
+                social("https://tidal.com/artist/64262665",          "/static/img/tidal.svg",  "Tidal"),
+                social("https://x.com/achtergesteld",                "/static/img/x.svg",      "X"),
+                social("https://twitch.tv/mentaalachtergesteld",     "/static/img/twitch.svg", "Twitch"),
+                social("https://github.com/mentaalachtergesteld",    "/static/img/github.svg", "GitHub"),
+            ],
+        }
+    }
+}
+
+/// Parses an override the way it would be written in the TOML file, so
+/// `8`, `true` and `["127.0.0.1"]` keep their types. Anything that isn't a
+/// valid TOML value is taken as a plain string.
+fn parse_env_value(raw: &str) -> Value {
+    format!("value = {raw}").parse::<Table>().ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| Value::String(raw.to_string()))
+}
+
+/// Keeps the raw string when only that fits the field, so
+/// `SITE_LASTFM__USERNAME=2024` doesn't turn into a number. Each override is
+/// tried against an otherwise default config to find out what the field takes.
+fn env_value(key: &str, raw: &str) -> Value {
+    let typed = parse_env_value(raw);
+    if typed.is_str() { return typed }
+
+    let fits = |value: &Value| {
+        let mut table = Table::new();
+        set_key(&mut table, key, value.clone()).is_ok()
+            && toml::from_str::<Config>(&table.to_string()).is_ok()
+    };
+    let raw = Value::String(raw.to_string());
+    if !fits(&typed) && fits(&raw) { raw } else { typed }
+}
+
+fn set_key(table: &mut Table, key: &str, value: Value) -> Result<(), ()> {
+    let mut segments: Vec<&str> = key.split('.').collect();
+    let last = segments.pop().unwrap_or_default();
+
+    let mut current = table;
+    for segment in segments {
+        let entry = current.entry(segment).or_insert_with(|| Value::Table(Table::new()));
+        current = entry.as_table_mut()
+            .ok_or_else(|| eprintln!("ERROR: Can't override `{key}`, `{segment}` is not a table"))?;
+    }
+
+    current.insert(last.to_string(), value);
+    Ok(())
+}
+
+/// Variables that aren't valid UTF-8 are skipped, unless they're one of ours.
+fn apply_env_overrides(table: &mut Table, vars: impl Iterator<Item = (OsString, OsString)>) -> Result<(), ()> {
+    let mut overrides: Vec<(String, String)> = Vec::new();
+
+    for (name, value) in vars {
+        let Ok(name) = name.into_string() else { continue };
+        let alias = ENV_ALIASES.iter().find(|(alias, _)| *alias == name);
+        let key = match (alias, name.strip_prefix(ENV_PREFIX).filter(|key| key.contains("__"))) {
+            (Some((_, key)), _) => key.to_string(),
+            (None, Some(key)) => key.to_ascii_lowercase().replace("__", "."),
+            (None, None) => continue,
+        };
+
+        let value = value.into_string()
+            .map_err(|_| eprintln!("ERROR: Environment variable `{name}` isn't valid UTF-8"))?;
+        if alias.is_some() {
+            overrides.insert(0, (key, value));
+        } else {
+            overrides.push((key, value));
+        }
+    }
+
+    // Aliases come first so an explicit `SITE_` variable wins over them.
+    for (key, value) in overrides {
+        set_key(table, &key, env_value(&key, &value))?;
+    }
+    Ok(())
+}
+
+impl Config {
+    /// `cache.snapshot_path`, or `cache-snapshot.json` in the directory of the
+    /// database. That directory has to survive deploys anyway, unlike the
+    /// working directory of a container.
+    pub fn snapshot_path(&self) -> PathBuf {
+        self.cache.snapshot_path.clone().unwrap_or_else(|| {
+            self.server.db_path.parent().unwrap_or(Path::new("")).join("cache-snapshot.json")
+        })
+    }
+
+    /// Loads the config file, applies environment overrides and then
+    /// `overrides` on top, and validates the result. Without an explicit
+    /// `path` a missing `config.toml` just means all defaults. The settings
+    /// only the web server needs, like the Last.fm key, are only checked
+    /// when `serving`.
+    pub fn load(path: Option<&Path>, overrides: &[(&str, String)], serving: bool) -> Result<Self, ()> {
+        let explicit = path.is_some();
+        let path = path.unwrap_or(Path::new(DEFAULT_PATH));
+
+        let mut table = match fs::read_to_string(path) {
+            Ok(content) => content.parse::<Table>()
+                .map_err(|e| eprintln!("ERROR: Couldn't parse `{}`:\n{e}", path.display()))?,
+            Err(e) if e.kind() == ErrorKind::NotFound && !explicit => Table::new(),
+            Err(e) => {
+                eprintln!("ERROR: Couldn't read `{}`: {e}", path.display());
+                return Err(());
+            }
+        };
+
+        apply_env_overrides(&mut table, env::vars_os())?;
+        for (key, value) in overrides {
+            set_key(&mut table, key, Value::String(value.clone()))?;
+        }
+
+        // Going through the serialized form keeps the key path in errors.
+        let config: Config = toml::from_str(&table.to_string())
+            .map_err(|e| eprintln!("ERROR: Invalid configuration in `{}` or its environment overrides:\n{e}", path.display()))?;
+
+        config.validate(serving)?;
+        Ok(config)
+    }
+
+    fn validate(&self, serving: bool) -> Result<(), ()> {
+        let mut errors = Vec::new();
+
+        if self.server.address.to_socket_addrs().is_err() {
+            errors.push(format!("server.address `{}` is not a valid socket address", self.server.address));
+        }
+        if self.server.threads == 0 {
+            errors.push("server.threads must be at least 1".into());
+        }
+        if self.server.queue_capacity == 0 {
+            errors.push("server.queue_capacity must be at least 1".into());
+        }
+
+        let ttls = [
+            ("now_playing_secs", self.cache.now_playing_secs),
+            ("top_artists_secs", self.cache.top_artists_secs),
+            ("top_tracks_secs",  self.cache.top_tracks_secs),
+            ("top_albums_secs",  self.cache.top_albums_secs),
+            ("user_stats_secs",  self.cache.user_stats_secs),
+            ("weather_secs",     self.cache.weather_secs),
+        ];
+        for (key, ttl) in ttls {
+            if ttl == 0 { errors.push(format!("cache.{key} must be at least 1")); }
+            else if self.cache.refresh_ahead_secs >= ttl {
+                errors.push(format!("cache.refresh_ahead_secs must be less than cache.{key}"));
+            }
+        }
+        for (key, secs) in ttls.into_iter().chain([("max_stale_secs", self.cache.max_stale_secs)]) {
+            if secs > MAX_CACHE_SECS {
+                errors.push(format!("cache.{key} can be at most {MAX_CACHE_SECS} (a year)"));
+            }
+        }
+
+        for field in self.security.invalid_headers() {
+            errors.push(format!("security: the {field} header can only contain printable ASCII"));
+        }
+
+        if self.access_log.max_bytes == 0 {
+            errors.push("access_log.max_bytes must be at least 1".into());
+        }
+        if self.ui.navbar.is_empty() {
+            errors.push("ui.navbar needs at least one item".into());
+        }
+        if serving {
+            errors.extend(self.serve_errors());
+        }
+
+        for error in &errors {
+            eprintln!("ERROR: Invalid configuration: {error}");
+        }
+        if errors.is_empty() { Ok(()) } else { Err(()) }
+    }
+
+    /// Problems with the settings that only matter to the web server.
+    fn serve_errors(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+
+        match Url::parse(&self.site.base_url) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+            _ => errors.push(format!("site.base_url `{}` must be an absolute http(s) URL", self.site.base_url)),
+        }
+        if self.site.og_images && !cfg!(feature = "og-image") {
+            errors.push("site.og_images needs a build with the `og-image` feature".into());
+        }
+        if self.lastfm.username.trim().is_empty() {
+            errors.push("lastfm.username can't be empty".into());
+        }
+        if self.lastfm.api_key.as_deref().is_none_or(|key| key.trim().is_empty()) {
+            errors.push("lastfm.api_key is missing, set it in the config file or through LASTFM_KEY".into());
+        }
+        if self.weather.location.trim().is_empty() {
+            errors.push("weather.location can't be empty".into());
+        }
+
+        if self.upstream.timeout_secs == 0 {
+            errors.push("upstream.timeout_secs must be at least 1".into());
+        }
+        if self.upstream.failure_threshold == 0 {
+            errors.push("upstream.failure_threshold must be at least 1".into());
+        }
+        if self.upstream.backoff_ms > MAX_BACKOFF_MS {
+            errors.push(format!("upstream.backoff_ms can be at most {MAX_BACKOFF_MS} (a minute)"));
+        }
+
+        errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use toml::{Table, Value};
+
+    use super::{Config, apply_env_overrides};
+
+    fn overrides(vars: &[(&str, &str)]) -> Table {
+        let mut table = Table::new();
+        let vars = vars.iter().map(|(name, value)| (name.into(), value.into()));
+        apply_env_overrides(&mut table, vars).unwrap();
+        table
+    }
+
+    fn valid() -> Config {
+        let mut config = Config::default();
+        config.lastfm.api_key = Some("key".into());
+        config
+    }
+
+    #[test]
+    fn overrides_nested_keys_with_their_types() {
+        let table = overrides(&[
+            ("SITE_SERVER__THREADS", "8"),
+            ("SITE_BLOG__SHOW_DRAFTS", "true"),
+            ("SITE_METRICS__ALLOW", "[\"10.0.0.1\"]"),
+            ("SITE_WEATHER__LOCATION", "Utrecht"),
+        ]);
+
+        assert_eq!(table["server"]["threads"], Value::Integer(8));
+        assert_eq!(table["blog"]["show_drafts"], Value::Boolean(true));
+        assert_eq!(table["metrics"]["allow"], Value::Array(vec![Value::String("10.0.0.1".into())]));
+        assert_eq!(table["weather"]["location"], Value::String("Utrecht".into()));
+    }
+
+    #[test]
+    fn keeps_strings_for_string_fields() {
+        let table = overrides(&[
+            ("SITE_LASTFM__USERNAME", "2024"),
+            ("SITE_LASTFM__API_KEY", "1234"),
+            ("SITE_SITE__TITLE", "2025-01-01"),
+        ]);
+
+        assert_eq!(table["lastfm"]["username"], Value::String("2024".into()));
+        assert_eq!(table["lastfm"]["api_key"], Value::String("1234".into()));
+        assert_eq!(table["site"]["title"], Value::String("2025-01-01".into()));
+        assert!(toml::from_str::<Config>(&table.to_string()).is_ok());
+    }
+
+    #[test]
+    fn ignores_unrelated_variables() {
+        let table = overrides(&[("SITE_URL", "x"), ("SITE_", "x"), ("PATH", "/usr/bin"), ("SERVER_PORT", "80")]);
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn prefixed_variables_win_over_aliases() {
+        let table = overrides(&[
+            ("SITE_LASTFM__API_KEY", "new"),
+            ("LASTFM_KEY", "old"),
+            ("SERVER_ADDRESS", "127.0.0.1:8080"),
+        ]);
+
+        assert_eq!(table["lastfm"]["api_key"], Value::String("new".into()));
+        assert_eq!(table["server"]["address"], Value::String("127.0.0.1:8080".into()));
+    }
+
+    #[test]
+    fn keeps_snapshot_next_to_database() {
+        let mut config = Config::default();
+        assert_eq!(config.snapshot_path(), PathBuf::from("cache-snapshot.json"));
+
+        config.server.db_path = "/data/guestbook.db".into();
+        assert_eq!(config.snapshot_path(), PathBuf::from("/data/cache-snapshot.json"));
+
+        config.cache.snapshot_path = Some("/tmp/snapshot.json".into());
+        assert_eq!(config.snapshot_path(), PathBuf::from("/tmp/snapshot.json"));
+    }
+
+    #[test]
+    fn accepts_defaults_with_a_key() {
+        assert!(valid().validate(true).is_ok());
+        assert!(Config::default().validate(true).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn skips_foreign_variables_that_arent_utf8() {
+        use std::{ffi::OsString, os::unix::ffi::OsStringExt};
+
+        let invalid = || OsString::from_vec(vec![b'S', 0xff]);
+        let mut table = Table::new();
+        let vars = [(invalid(), "x".into()), ("PATH".into(), invalid()), ("SITE_SERVER__THREADS".into(), "8".into())];
+        apply_env_overrides(&mut table, vars.into_iter()).unwrap();
+        assert_eq!(table["server"]["threads"], Value::Integer(8));
+
+        let vars = [("SITE_WEATHER__LOCATION".into(), invalid())];
+        assert!(apply_env_overrides(&mut Table::new(), vars.into_iter()).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        let invalid: [fn(&mut Config); 9] = [
+            |config| config.server.address = "nowhere".into(),
+            |config| config.server.threads = 0,
+            |config| config.site.base_url = "/relative".into(),
+            |config| config.lastfm.username = " ".into(),
+            |config| config.cache.weather_secs = 0,
+            |config| config.cache.refresh_ahead_secs = config.cache.now_playing_secs,
+            |config| config.cache.max_stale_secs = u64::MAX,
+            |config| config.ui.navbar.clear(),
+            |config| config.upstream.backoff_ms = u64::MAX,
+        ];
+
+        for change in invalid {
+            let mut config = valid();
+            change(&mut config);
+            assert!(config.validate(true).is_err());
+        }
+    }
+
+    #[test]
+    fn only_checks_server_settings_when_serving() {
+        let mut config = Config::default();
+        config.site.base_url = "/relative".into();
+        config.upstream.timeout_secs = 0;
+        assert!(config.validate(false).is_ok());
+
+        config.server.threads = 0;
+        assert!(config.validate(false).is_err());
+    }
+}
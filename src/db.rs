@@ -27,6 +27,55 @@ impl MessageDb {
         })
     }
 
+    pub fn close(self) -> Result<(), ()> {
+        self.connection.close()
+            .map_err(|(_, e)| eprintln!("ERROR: Couldn't close database: {e}"))
+    }
+
+    pub fn ping(&self) -> Result<(), ()> {
+        self.connection.query_row("SELECT 1", [], |row| row.get::<_, i64>(0))
+            .map(|_| ())
+            .map_err(|e| eprintln!("ERROR: Database ping failed: {e}"))
+    }
+
+    /// Runs SQLite's integrity check and returns the problems it found.
+    pub fn check(&self) -> Result<Vec<String>, ()> {
+        let mut stmt = self.connection.prepare("PRAGMA integrity_check")
+            .map_err(|e| eprintln!("ERROR: Couldn't prepare integrity check: {e}"))?;
+
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))
+            .map_err(|e| eprintln!("ERROR: Couldn't run integrity check: {e}"))?;
+        let rows = rows.collect::<Result<Vec<String>, _>>()
+            .map_err(|e| eprintln!("ERROR: Couldn't read integrity check results: {e}"))?;
+
+        Ok(rows.into_iter().filter(|row| row != "ok").collect())
+    }
+
+    pub fn vacuum(&self) -> Result<(), ()> {
+        self.connection.execute("VACUUM", [])
+            .map(|_| ())
+            .map_err(|e| eprintln!("ERROR: Couldn't vacuum database: {e}"))
+    }
+
+    /// Writes a consistent copy to `target`, which must not exist yet.
+    pub fn backup(&self, target: &Path) -> Result<(), ()> {
+        if target.exists() {
+            eprintln!("ERROR: Backup target `{}` already exists", target.display());
+            return Err(());
+        }
+
+        self.connection.execute("VACUUM INTO ?1", [target.to_string_lossy()])
+            .map(|_| ())
+            .map_err(|e| eprintln!("ERROR: Couldn't back up database to `{}`: {e}", target.display()))
+    }
+
+    /// Returns whether a message with that id existed.
+    pub fn delete_message(&self, id: i64) -> Result<bool, ()> {
+        self.connection.execute("DELETE FROM messages WHERE id = ?1", [id])
+            .map(|deleted| deleted > 0)
+            .map_err(|e| eprintln!("ERROR: Couldn't delete message {id}: {e}"))
+    }
+
     fn parse_message(row: &Row<'_>) -> rusqlite::Result<Message> {
         let timestamp_str: String = row.get(3)?;
         let timestamp = DateTime::parse_from_rfc3339(&timestamp_str)
@@ -0,0 +1,254 @@
+use std::fmt::Write;
+
+use chrono::{DateTime, NaiveDate, Utc};
+
+use crate::{assets, blog::Post, config::{NavItem, SiteConfig}, models::Project, state::SiteText};
+
+/// The most entries `/feed.atom` lists, newest first.
+const FEED_LIMIT: usize = 50;
+
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn rfc3339(date: NaiveDate) -> String {
+    date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc().to_rfc3339()
+}
+
+/// Modification date of a file under the static root, `None` when it's
+/// embedded.
+fn file_date(rel_path: &str) -> Option<NaiveDate> {
+    assets::modified(rel_path).map(|modified| DateTime::<Utc>::from(modified).date_naive())
+}
+
+pub fn robots(site: &SiteConfig) -> String {
+    format!(
+        "User-agent: *\nAllow: /\nDisallow: /comp/\n\nSitemap: {}\n",
+        site.url("/sitemap.xml"),
+    )
+}
+
+pub fn sitemap(site: &SiteConfig, navbar: &[NavItem], projects: &[Project], posts: &[Post]) -> String {
+    let posts: Vec<&Post> = posts.iter().filter(|post| !post.draft).collect();
+    let newest_post = posts.iter().map(|post| post.updated.unwrap_or(post.date)).max();
+    let projects_date = file_date("projects.toml");
+
+    let mut urls: Vec<(String, Option<NaiveDate>)> = Vec::new();
+    for item in navbar {
+        // External links in the navbar aren't ours to list.
+        if !item.href.starts_with('/') { continue }
+
+        let lastmod = match item.href.as_str() {
+            "/" | "/home" => SiteText::FILES.into_iter().filter_map(file_date).max(),
+            "/projects" => projects_date,
+            "/blog" => newest_post,
+            _ => None,
+        };
+        urls.push((item.href.clone(), lastmod));
+    }
+
+    for project in projects {
+        let lastmod = project.page.as_deref().and_then(file_date).or(projects_date);
+        urls.push((format!("/projects/{}", project.slug), lastmod));
+    }
+    for post in posts {
+        urls.push((format!("/blog/{}", post.slug), Some(post.updated.unwrap_or(post.date))));
+    }
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n");
+    for (path, lastmod) in urls {
+        let _ = write!(xml, "  <url><loc>{}</loc>", xml_escape(&site.url(&path)));
+        if let Some(lastmod) = lastmod {
+            let _ = write!(xml, "<lastmod>{}</lastmod>", lastmod.format("%Y-%m-%d"));
+        }
+        xml.push_str("</url>\n");
+    }
+    xml.push_str("</urlset>\n");
+    xml
+}
+
+struct FeedEntry {
+    title: String,
+    path: String,
+    published: NaiveDate,
+    updated: NaiveDate,
+    summary: Option<String>,
+    content: Option<String>,
+    categories: Vec<String>,
+}
+
+/// Blog posts and projects that have a `started` date.
+pub fn atom(site: &SiteConfig, projects: &[Project], posts: &[Post]) -> String {
+    let mut entries = Vec::new();
+
+    for post in posts.iter().filter(|post| !post.draft) {
+        entries.push(FeedEntry {
+            title: post.title.clone(),
+            path: format!("/blog/{}", post.slug),
+            published: post.date,
+            updated: post.updated.unwrap_or(post.date),
+            summary: post.summary.clone(),
+            content: Some(post.document.html.clone()),
+            categories: post.tags.clone(),
+        });
+    }
+    for project in projects {
+        let Some((year, month, day)) = project.started_on() else { continue };
+        let Some(started) = NaiveDate::from_ymd_opt(year.into(), month.into(), day.into()) else { continue };
+
+        entries.push(FeedEntry {
+            title: format!("New project: {}", project.title),
+            path: format!("/projects/{}", project.slug),
+            published: started,
+            updated: started,
+            summary: Some(project.description.clone()),
+            content: None,
+            categories: project.tags.clone(),
+        });
+    }
+
+    entries.sort_by(|a, b| b.updated.cmp(&a.updated).then_with(|| a.title.cmp(&b.title)));
+    entries.truncate(FEED_LIMIT);
+
+    let updated = entries.iter().map(|entry| rfc3339(entry.updated)).next()
+        .unwrap_or_else(|| Utc::now().to_rfc3339());
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    let _ = writeln!(xml, "  <id>{}</id>", xml_escape(&site.url("/")));
+    let _ = writeln!(xml, "  <title>{}</title>", xml_escape(&site.title));
+    let _ = writeln!(xml, "  <updated>{updated}</updated>");
+    let _ = writeln!(xml, "  <author><name>{}</name></author>", xml_escape(&site.author));
+    let _ = writeln!(xml, "  <link rel=\"self\" type=\"application/atom+xml\" href=\"{}\"/>", xml_escape(&site.url("/feed.atom")));
+    let _ = writeln!(xml, "  <link rel=\"alternate\" type=\"text/html\" href=\"{}\"/>", xml_escape(&site.url("/")));
+
+    for entry in entries {
+        let url = xml_escape(&site.url(&entry.path));
+        xml.push_str("  <entry>\n");
+        let _ = writeln!(xml, "    <id>{url}</id>");
+        let _ = writeln!(xml, "    <title>{}</title>", xml_escape(&entry.title));
+        let _ = writeln!(xml, "    <link rel=\"alternate\" type=\"text/html\" href=\"{url}\"/>");
+        let _ = writeln!(xml, "    <published>{}</published>", rfc3339(entry.published));
+        let _ = writeln!(xml, "    <updated>{}</updated>", rfc3339(entry.updated));
+        for category in &entry.categories {
+            let _ = writeln!(xml, "    <category term=\"{}\"/>", xml_escape(category));
+        }
+        if let Some(summary) = &entry.summary {
+            let _ = writeln!(xml, "    <summary>{}</summary>", xml_escape(summary));
+        }
+        if let Some(content) = &entry.content {
+            let _ = writeln!(xml, "    <content type=\"html\">{}</content>", xml_escape(content));
+        }
+        xml.push_str("  </entry>\n");
+    }
+
+    xml.push_str("</feed>\n");
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use crate::{blog::Post, config::{NavItem, SiteConfig}, markdown::Document, models::{Project, parse_projects}};
+
+    use super::{atom, sitemap};
+
+    fn site() -> SiteConfig {
+        SiteConfig { base_url: "https://example.com/".into(), title: "Tom & Jerry's".into(), ..SiteConfig::default() }
+    }
+
+    fn post(slug: &str, date: (i32, u32, u32), draft: bool) -> Post {
+        Post {
+            slug: slug.into(),
+            title: format!("<{slug}>"),
+            date: NaiveDate::from_ymd_opt(date.0, date.1, date.2).unwrap(),
+            updated: None,
+            tags: vec!["a&b".into()],
+            draft,
+            summary: None,
+            document: Document { html: "<p>Hi &amp; bye</p>".into(), toc: Vec::new() },
+        }
+    }
+
+    fn projects() -> Vec<Project> {
+        parse_projects(r#"
+            [[project]]
+            title = "Q&A"
+            description = "Ask <anything>"
+            source_url = ""
+            started = 2024-06-01
+
+            [[project]]
+            title = "Undated"
+            description = ""
+            source_url = ""
+        "#).unwrap()
+    }
+
+    #[test]
+    fn lists_entries_newest_first_without_drafts() {
+        let posts = [post("old", (2024, 1, 1), false), post("draft", (2025, 5, 5), true), post("new", (2025, 2, 1), false)];
+        let xml = atom(&site(), &projects(), &posts);
+
+        let ids: Vec<&str> = xml.lines()
+            .filter_map(|line| line.trim().strip_prefix("<id>")?.strip_suffix("</id>"))
+            .collect();
+        assert_eq!(ids, [
+            "https://example.com/",
+            "https://example.com/blog/new",
+            "https://example.com/projects/q-a",
+            "https://example.com/blog/old",
+        ]);
+        assert!(xml.contains("  <updated>2025-02-01T00:00:00+00:00</updated>\n"));
+    }
+
+    #[test]
+    fn escapes_feed_text() {
+        let xml = atom(&site(), &projects(), &[post("new", (2025, 2, 1), false)]);
+
+        assert!(xml.contains("<title>Tom &amp; Jerry&apos;s</title>"));
+        assert!(xml.contains("<title>&lt;new&gt;</title>"));
+        assert!(xml.contains("<title>New project: Q&amp;A</title>"));
+        assert!(xml.contains("<summary>Ask &lt;anything&gt;</summary>"));
+        assert!(xml.contains("<category term=\"a&amp;b\"/>"));
+        assert!(xml.contains("<content type=\"html\">&lt;p&gt;Hi &amp;amp; bye&lt;/p&gt;</content>"));
+    }
+
+    #[test]
+    fn maps_local_pages_projects_and_published_posts() {
+        let navbar = [
+            NavItem { href: "/blog".into(), label: "Blog".into() },
+            NavItem { href: "https://elsewhere.example".into(), label: "Elsewhere".into() },
+            NavItem { href: "/search?q=a&b".into(), label: "Search".into() },
+        ];
+        let posts = [post("new", (2025, 2, 1), false), post("draft", (2025, 5, 5), true)];
+        let xml = sitemap(&site(), &navbar, &projects(), &posts);
+
+        let locs: Vec<&str> = xml.lines()
+            .filter_map(|line| line.split_once("<loc>")?.1.split_once("</loc>").map(|(loc, _)| loc))
+            .collect();
+        assert_eq!(locs, [
+            "https://example.com/blog",
+            "https://example.com/search?q=a&amp;b",
+            "https://example.com/projects/q-a",
+            "https://example.com/projects/undated",
+            "https://example.com/blog/new",
+        ]);
+        // The draft is newer, but doesn't count for the blog index either.
+        assert!(xml.contains("<loc>https://example.com/blog</loc><lastmod>2025-02-01</lastmod>"));
+        assert!(xml.contains("<loc>https://example.com/blog/new</loc><lastmod>2025-02-01</lastmod>"));
+    }
+}
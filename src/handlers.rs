@@ -1,50 +1,120 @@
-use std::{collections::HashMap, fs::File, io::Read, path::Path, str::FromStr, sync::{Arc, Mutex}};
+use std::{collections::HashMap, io::{Cursor, Read}, net::IpAddr, str::FromStr, sync::Arc, time::Instant};
 
+use chrono::{NaiveDate, Utc};
 use maud::{Markup, html};
-use tiny_http::{Header, Method, Request, Response};
+use tiny_http::{Header, Method, Request, Response, ResponseBox};
 use url::form_urlencoded;
 
-use crate::{models::Project, state::App, ui::{self, components, pages::{self, not_found}}, util::{parse_query, rate_limiter::get_client_ip}};
-
-fn send_response<R: Read>(req: Request, res: Response<R>) -> Result<(), ()> {
-    req.respond(res)
-        .map_err(|e| eprintln!("ERROR: Couldn't respond: {e}"))
+#[cfg(feature = "og-image")]
+use crate::og_image;
+use crate::{access_log::{self, AccessEntry}, api::lastfm::Period, assets::{self, Asset, AssetData}, blog::{Blog, Post}, feeds, health, metrics, models::{Project, ProjectFacets, ProjectQuery}, security, state::App, ui::{self, OgType, PageMeta, components, pages::{self, not_found}}, util::{LockExt, parse_query, rate_limiter::get_client_ip}};
+
+pub struct RequestContext {
+    pub app: Arc<App>,
+    pub nonce: String,
+    pub request_id: String,
+    pub started: Instant,
+    method: String,
+    path: String,
+    client_ip: Option<IpAddr>,
+    user_agent: Option<String>,
+    htmx: bool,
 }
 
-fn handle_static(req: Request) -> Result<(), ()> {
-    let rel_path = req.url().trim_start_matches("/");
-    if rel_path.contains("..") {
-        return send_response(req, Response::empty(404))
+impl RequestContext {
+    pub fn new(req: &Request, app: Arc<App>) -> Self {
+        let incoming_id = find_header(req, "X-Request-Id");
+
+        Self {
+            app,
+            nonce: security::generate_nonce(),
+            request_id: access_log::request_id(incoming_id),
+            started: Instant::now(),
+            method: req.method().to_string(),
+            path: req.url().to_string(),
+            client_ip: get_client_ip(req),
+            user_agent: find_header(req, "User-Agent").map(str::to_string),
+            htmx: is_htmx(req),
+        }
     }
 
-    let path = Path::new("./").join(rel_path);
-
-    if !path.exists() || !path.is_file() {
-        return send_response(req, Response::empty(404)) 
+    /// Records a finished request in the metrics and the access log.
+    pub fn record(&self, status: u16, size: Option<usize>) {
+        let latency = self.started.elapsed();
+        self.app.metrics.observe_request(metrics::route_label(&self.path), status, latency);
+
+        self.app.access_log.write(AccessEntry {
+            timestamp: Utc::now(),
+            client_ip: self.client_ip,
+            method: &self.method,
+            path: &self.path,
+            status,
+            size,
+            latency,
+            user_agent: self.user_agent.as_deref(),
+            htmx: self.htmx,
+            request_id: &self.request_id,
+        });
     }
+}
+
+fn find_header<'a>(req: &'a Request, field: &'static str) -> Option<&'a str> {
+    req.headers().iter()
+        .find(|h| h.field.equiv(field))
+        .map(|h| h.value.as_str())
+}
+
+fn is_htmx(req: &Request) -> bool {
+    find_header(req, "HX-Request").is_some()
+}
+
+pub fn send_response<R: Read>(ctx: &RequestContext, req: Request, res: Response<R>) -> Result<(), ()> {
+    let res = ctx.app.security.apply(res, &ctx.nonce)
+        .with_header(Header::from_str(&format!("X-Request-Id: {}", ctx.request_id)).unwrap());
+
+    let status = res.status_code().0;
+    let size = res.data_length();
+
+    let result = req.respond(res)
+        .map_err(|e| eprintln!("ERROR: Couldn't respond: {e}"));
+
+    ctx.record(status, size);
+    result
+}
 
-    let file = File::open(&path).map_err(|e| eprintln!("ERROR: Couldn't open file: {e}"))?;
+fn with_asset_headers<R: Read>(res: Response<R>, content_type: &str, etag: &str) -> Response<R> {
+    res.with_header(Header::from_str(&format!("Content-Type: {content_type}")).unwrap())
+        .with_header(Header::from_str(&format!("ETag: {etag}")).unwrap())
+        .with_header(Header::from_str("Cache-Control: public, max-age=86400").unwrap())
+}
 
-    let content_type = match path.extension().and_then(|ext| ext.to_str()) {
-        Some("css") => "text/css; charset=utf-8",
-        Some("js")  => "application/javascript; charset=utf-8",
-        Some("png") => "image/png",
-        Some("gif") => "image/gif",
-        Some("svg") => "image/svg+xml",
-        Some("jpg") | Some("jpeg") => "image/jpeg",
-        _           => "application/octet-stream"
+fn handle_static(req: &Request) -> ResponseBox {
+    let url = req.url().split("?").next().unwrap_or("");
+    let Some(rel_path) = url.strip_prefix("/static/") else {
+        return Response::empty(404).boxed()
     };
+    let Some(Asset { data, content_type, etag }) = assets::load(rel_path) else {
+        return Response::empty(404).boxed()
+    };
+
+    let is_fresh = find_header(req, "If-None-Match") == Some(etag.as_str());
 
-    let response = Response::from_file(file)
-        .with_header(Header::from_str(&format!("Content-Type: {content_type}")).unwrap())
-        .with_header(Header::from_str("Cache-Control: public, max-age=86400").unwrap());
+    if is_fresh {
+        return with_asset_headers(Response::empty(304), content_type, &etag).boxed()
+    }
 
-    send_response(req, response)
+    match data {
+        AssetData::File(file) => with_asset_headers(Response::from_file(file), content_type, &etag).boxed(),
+        AssetData::Embedded(bytes) => {
+            let response = Response::new(200.into(), vec![], Cursor::new(bytes), Some(bytes.len()), None);
+            with_asset_headers(response, content_type, &etag).boxed()
+        }
+    }
 }
 
-fn process_post_message(req: &mut Request, app: Arc<App>) -> Markup {
-    let is_allowed = get_client_ip(&req)
-        .map_or(false, |ip| app.rate_limiter.lock().unwrap().is_allowed(ip));
+fn process_post_message(req: &mut Request, app: &App) -> Markup {
+    let is_allowed = get_client_ip(req)
+        .is_some_and(|ip| app.rate_limiter.lock_recover().is_allowed(ip));
 
     if !is_allowed {
         return components::form_feedback("Rate limited", "You're being too fast! Try again in a few seconds.", true);
@@ -67,9 +137,10 @@ fn process_post_message(req: &mut Request, app: Arc<App>) -> Markup {
         return components::form_feedback("Content empty", "No content supplied", false);
     }
 
-    let Ok(msg) = app.message_db.lock().unwrap().create_message(author, content) else {
+    let Ok(msg) = app.message_db.lock_recover().create_message(author, content) else {
         return components::form_feedback("Error while creating message", "The server could not create your message", true);
     };
+    app.metrics.inc_guestbook_posts();
 
     html! {
         (components::message_item(&msg));
@@ -77,38 +148,52 @@ fn process_post_message(req: &mut Request, app: Arc<App>) -> Markup {
     }
 }
 
-pub fn handle_comp(mut req: Request, app: Arc<App>) -> Result<(), ()> {
+fn handle_comp(req: &mut Request, ctx: &RequestContext) -> ResponseBox {
+    let app = &ctx.app;
     let method = req.method();
     let url = req.url().split("?").next().unwrap_or("");
 
-
+    // Fetchers can run on a background refresh thread, so they take their
+    // own handle to the API client. Errors are turned into `None`, which
+    // the caches never store.
     let content = match (method, url) {
+        (Method::Get, "/comp/lastfm-stats") => components::lastfm_stats(Period::from_params(&parse_query(req.url()))),
         (Method::Get, "/comp/now-playing") => {
-            let data = app.lastfm_cache.now_playing.get_or_update(|| Some(app.lastfm.get_now_playing()));
-            components::now_playing(data.as_deref())
+            let lastfm = Arc::clone(&app.lastfm);
+            let data = app.lastfm_cache.now_playing.get_or_update(move || lastfm.get_now_playing().ok());
+            components::now_playing(data.as_deref().into())
         },
         (Method::Get, "/comp/top-artists") => {
-            let data = app.lastfm_cache.top_artists.get_or_update(|| Some(app.lastfm.get_top_artists(10, "1month")));
-            components::top_artists(data.as_deref())
+            let period = Period::from_params(&parse_query(req.url()));
+            let lastfm = Arc::clone(&app.lastfm);
+            let data = app.lastfm_cache.top_artists.get_or_update(period, move || lastfm.get_top_artists(10, period).ok());
+            components::top_artists(data.as_deref().into(), period)
         },
-        (Method::Get, "/comp/top-tracks")  => {
-            let data = app.lastfm_cache.top_tracks.get_or_update(|| Some(app.lastfm.get_top_tracks(10, "1month")));
-            components::top_tracks(data.as_deref())
+        (Method::Get, "/comp/top-tracks") => {
+            let period = Period::from_params(&parse_query(req.url()));
+            let lastfm = Arc::clone(&app.lastfm);
+            let data = app.lastfm_cache.top_tracks.get_or_update(period, move || lastfm.get_top_tracks(10, period).ok());
+            components::top_tracks(data.as_deref().into(), period)
         }
-        (Method::Get, "/comp/top-albums")  => {
-            let data = app.lastfm_cache.top_albums.get_or_update(|| Some(app.lastfm.get_top_albums(10, "1month")));
-            components::top_albums(data.as_deref())
+        (Method::Get, "/comp/top-albums") => {
+            let period = Period::from_params(&parse_query(req.url()));
+            let lastfm = Arc::clone(&app.lastfm);
+            let data = app.lastfm_cache.top_albums.get_or_update(period, move || lastfm.get_top_albums(10, period).ok());
+            components::top_albums(data.as_deref().into(), period)
         }
         (Method::Get, "/comp/user-stats")  => {
-            let data = app.lastfm_cache.user_stats.get_or_update(|| app.lastfm.get_user_stats());
-            components::lastfm_user_stats(data.as_deref())
+            let lastfm = Arc::clone(&app.lastfm);
+            let data = app.lastfm_cache.user_stats.get_or_update(move || lastfm.get_user_stats().ok());
+            components::lastfm_user_stats(data.as_deref().into())
         }
         (Method::Get, "/comp/server-weather") => {
-            let data = app.wttr_cache.weather.get_or_update(|| Some(app.wttr.get_weather()));
-            components::server_weather(data.as_deref())
+            let wttr = Arc::clone(&app.wttr);
+            let data = app.wttr_cache.weather.get_or_update(move || wttr.get_weather().ok());
+            components::server_weather(data.as_deref().into())
         }
         (Method::Get, "/comp/projects") => {
             let queries = parse_query(req.url());
+            let query = ProjectQuery::from_params(&queries);
 
             let start_index = queries.get("last_id")
                 .and_then(|v| v.parse::<usize>().ok())
@@ -117,16 +202,20 @@ pub fn handle_comp(mut req: Request, app: Arc<App>) -> Result<(), ()> {
                 .and_then(|v| v.parse::<usize>().ok())
                 .unwrap_or(5);
 
-            let (projects, next_index) = if start_index >= app.projects.len() {
-                (&[] as &[Project], None)
+            // `last_id` indexes into the filtered and sorted list, so the
+            // load more trigger has to carry the same filters.
+            let all_projects = app.projects.get();
+            let matching = query.apply(&all_projects);
+            let start = start_index.min(matching.len());
+            let end = matching.len().min(start + limit);
+            let next_index = if end < matching.len() { Some(end) } else { None };
+            let projects = &matching[start..end];
+
+            if queries.contains_key("last_id") {
+                components::projects_list(projects, next_index, &query)
             } else {
-                let end = app.projects.len().min(start_index+limit);
-                let slice = &app.projects[start_index..end];
-                let next_index = if end < app.projects.len() { Some(end) } else { None };
-                (slice, next_index)
-            };
-
-            components::projects_list(projects, next_index)
+                components::project_browser(&ProjectFacets::collect(&all_projects), &query, projects, next_index)
+            }
         },
         (Method::Get, "/comp/messages") => {
             let queries = parse_query(req.url());
@@ -139,7 +228,7 @@ pub fn handle_comp(mut req: Request, app: Arc<App>) -> Result<(), ()> {
                 .and_then(|v| v.parse::<i64>().ok())
                 .unwrap_or(5);
 
-            let messages = app.message_db.lock().unwrap()
+            let messages = app.message_db.lock_recover()
                 .read_messages(start_index, limit)
                 .unwrap_or(vec![]);
 
@@ -149,50 +238,319 @@ pub fn handle_comp(mut req: Request, app: Arc<App>) -> Result<(), ()> {
 
             components::message_list(&messages, next_index)
         },
-        (Method::Post, "/comp/messages") => process_post_message(&mut req, app),
+        (Method::Post, "/comp/messages") => process_post_message(req, app),
         _ => {
 
             let body = not_found().into_string();
-            let response = Response::from_string(body)
+            return Response::from_string(body)
                 .with_header(Header::from_str("Content-Type: text/html; charset=utf-8").unwrap())
-                .with_status_code(404);
-            return send_response(req, response)
+                .with_status_code(404)
+                .boxed()
         }
     };
 
     let body = content.into_string();
-    let response = Response::from_string(body)
-        .with_header(Header::from_str("Content-Type: text/html; charset=utf-8").unwrap());
+    Response::from_string(body)
+        .with_header(Header::from_str("Content-Type: text/html; charset=utf-8").unwrap())
+        .boxed()
+}
+
+fn json(status: u16, body: serde_json::Value) -> ResponseBox {
+    Response::from_string(body.to_string())
+        .with_header(Header::from_str("Content-Type: application/json").unwrap())
+        .with_header(Header::from_str("Cache-Control: no-store").unwrap())
+        .with_status_code(status)
+        .boxed()
+}
+
+fn text(content_type: &str, body: String) -> ResponseBox {
+    Response::from_string(body)
+        .with_header(Header::from_bytes("Content-Type", content_type).unwrap())
+        .boxed()
+}
+
+/// Only answers direct connections from the allowed addresses; anything that
+/// came in through the reverse proxy gets a plain 404.
+fn handle_metrics(req: &Request, ctx: &RequestContext) -> ResponseBox {
+    let is_proxied = find_header(req, "CF-Connecting-IP").is_some()
+        || find_header(req, "X-Forwarded-For").is_some();
+    let is_allowed = !is_proxied && req.remote_addr()
+        .is_some_and(|addr| ctx.app.metrics.allowed.contains(&addr.ip()));
+
+    if !is_allowed {
+        return Response::empty(404).boxed()
+    }
+
+    Response::from_string(metrics::render(&ctx.app))
+        .with_header(Header::from_str("Content-Type: text/plain; version=0.0.4; charset=utf-8").unwrap())
+        .boxed()
+}
+
+/// Answers straight from the accept loop when every worker is busy and the
+/// queue is full.
+pub fn handle_overload(req: Request, ctx: &RequestContext, retry_after: u64) -> Result<(), ()> {
+    let response = Response::from_string("Server is busy, please try again in a few seconds.")
+        .with_header(Header::from_str("Content-Type: text/plain; charset=utf-8").unwrap())
+        .with_header(Header::from_str(&format!("Retry-After: {retry_after}")).unwrap())
+        .with_status_code(503);
+
+    send_response(ctx, req, response)
+}
+
+/// Answers a request whose handler panicked.
+pub fn internal_error() -> ResponseBox {
+    Response::from_string("Internal Server Error")
+        .with_header(Header::from_str("Content-Type: text/plain; charset=utf-8").unwrap())
+        .with_status_code(500)
+        .boxed()
+}
+
+/// A full page, rendered inside the layout unless htmx asked for it.
+struct Page {
+    title: String,
+    content: Markup,
+    status: u16,
+    meta: PageMeta,
+}
+
+impl Page {
+    fn new(title: &str, path: &str, content: Markup) -> Self {
+        Self { title: title.to_string(), content, status: 200, meta: PageMeta::new(path) }
+    }
+
+    fn not_found(path: &str) -> Self {
+        Self {
+            status: 404,
+            meta: PageMeta { noindex: true, ..PageMeta::new(path) },
+            ..Self::new("Not Found", path, pages::not_found())
+        }
+    }
+}
+
+fn project_page(app: &App, path: &str, slug: &str) -> Page {
+    let projects = app.projects.get();
+    let Some(project) = projects.iter().find(|p| p.slug == slug) else {
+        return Page::not_found(path);
+    };
+
+    Page {
+        meta: project_meta(path, project),
+        ..Page::new(&project.title, path, pages::project(project, project.document.as_ref()))
+    }
+}
+
+fn project_meta(path: &str, project: &Project) -> PageMeta {
+    let started = project.started_on()
+        .and_then(|(year, month, day)| NaiveDate::from_ymd_opt(year.into(), month.into(), day.into()));
+
+    PageMeta {
+        description: Some(project.description.clone()),
+        image: project.image_url.clone(),
+        og_type: OgType::Article,
+        published: started,
+        ..PageMeta::new(path)
+    }
+}
+
+fn blog_index(app: &App, url: &str) -> Page {
+    let queries = parse_query(url);
+    let active_tag = queries.get("tag").map(String::as_str).filter(|tag| !tag.is_empty());
+
+    let posts = app.blog.posts();
+    let matching: Vec<&Post> = posts.iter()
+        .filter(|post| active_tag.is_none_or(|tag| post.tags.iter().any(|t| t == tag)))
+        .collect();
+
+    Page::new("Blog", "/blog", pages::blog(&matching, &Blog::tags(&posts), active_tag))
+}
+
+fn post_page(app: &App, path: &str, slug: &str) -> Page {
+    let posts = app.blog.posts();
+    let Some(post) = posts.iter().find(|post| post.slug == slug) else {
+        return Page::not_found(path);
+    };
+
+    Page {
+        meta: post_meta(path, post),
+        ..Page::new(&post.title, path, pages::post(post))
+    }
+}
+
+fn post_meta(path: &str, post: &Post) -> PageMeta {
+    PageMeta {
+        description: post.summary.clone(),
+        og_type: OgType::Article,
+        published: Some(post.date),
+        modified: post.updated,
+        ..PageMeta::new(path)
+    }
+}
+
+/// Title of the page at `path`, for the generated preview images.
+#[cfg(feature = "og-image")]
+fn page_title(app: &App, path: &str) -> Option<String> {
+    if let Some(slug) = path.strip_prefix("/projects/") {
+        return app.projects.get().iter().find(|p| p.slug == slug).map(|p| p.title.clone());
+    }
+    if let Some(slug) = path.strip_prefix("/blog/") {
+        return app.blog.posts().iter().find(|post| post.slug == slug).map(|post| post.title.clone());
+    }
+    app.ui.navbar.iter().find(|item| item.href == path).map(|item| item.label.clone())
+}
+
+#[cfg(feature = "og-image")]
+fn handle_og_image(req: &Request, ctx: &RequestContext) -> ResponseBox {
+    let path = req.url().split("?").next().unwrap_or("");
+    let page_path = path.strip_prefix("/og").and_then(|p| p.strip_suffix(".png")).unwrap_or("");
+
+    let image = page_title(&ctx.app, page_path)
+        .and_then(|title| og_image::render(&title, &ctx.app.site.title));
+    let Some(image) = image else {
+        return Response::from_string("Not Found").with_status_code(404).boxed();
+    };
 
-    send_response(req, response)
+    Response::from_data(image.as_slice())
+        .with_header(Header::from_str("Content-Type: image/png").unwrap())
+        .with_header(Header::from_str("Cache-Control: public, max-age=86400").unwrap())
+        .boxed()
 }
 
-pub fn handle_request(req: Request, app: Arc<App>) -> Result<(), ()> {
+/// Builds the response to `req`. Sending it is up to the caller, so a
+/// panicking handler still leaves a request to answer.
+pub fn handle_request(req: &mut Request, ctx: &RequestContext) -> ResponseBox {
+    let path = req.url().split("?").next().unwrap_or("");
+
     if req.url().starts_with("/static") {return handle_static(req)};
-    if req.url().starts_with("/comp")   {return handle_comp(req, app)};
+    if req.url().starts_with("/comp")   {return handle_comp(req, ctx)};
+    #[cfg(feature = "og-image")]
+    if req.url().starts_with("/og/") && ctx.app.site.og_images {return handle_og_image(req, ctx)};
+    if path == "/metrics"               {return handle_metrics(req, ctx)};
+    if path == "/robots.txt" {
+        return text("text/plain; charset=utf-8", feeds::robots(&ctx.app.site));
+    }
+    if path == "/sitemap.xml" {
+        let xml = feeds::sitemap(&ctx.app.site, &ctx.app.ui.navbar, &ctx.app.projects.get(), &ctx.app.blog.posts());
+        return text("application/xml; charset=utf-8", xml);
+    }
+    if path == "/feed.atom" {
+        let xml = feeds::atom(&ctx.app.site, &ctx.app.projects.get(), &ctx.app.blog.posts());
+        return text("application/atom+xml; charset=utf-8", xml);
+    }
+    if path == "/healthz"               {return json(200, health::liveness())};
+    if path == "/readyz" {
+        let (is_ready, body) = health::readiness(&ctx.app);
+        return json(if is_ready { 200 } else { 503 }, body)
+    }
 
     let method = req.method();
     let url = req.url().split("?").next().unwrap_or("");
 
-    let (title, content, status) = match (method, url) {
-        (Method::Get, "/" | "/home") => ("Home",      pages::home(), 200),
-        (Method::Get, "/guestbook") =>  ("Guestbook", pages::guestbook(), 200),
-        (Method::Get, "/projects") =>   ("Projects",  pages::projects(), 200),
-        (Method::Get, "/interests") =>  ("Interests", pages::interests(), 200),
-        _ => ("Not Found", pages::not_found(), 404),
+    let page = match (method, url) {
+        (Method::Get, "/" | "/home") => Page::new("Home",      "/home",      pages::home(&ctx.app.text, &ctx.app.ui.socials)),
+        (Method::Get, "/guestbook") =>  Page::new("Guestbook", "/guestbook", pages::guestbook()),
+        (Method::Get, "/projects") =>   Page::new("Projects",  "/projects",  pages::projects(&ProjectQuery::from_params(&parse_query(req.url())))),
+        (Method::Get, "/interests") =>  Page::new("Interests", "/interests", pages::interests(Period::from_params(&parse_query(req.url())))),
+        (Method::Get, "/blog") =>       blog_index(&ctx.app, req.url()),
+        (Method::Get, path) if path.starts_with("/projects/") => project_page(&ctx.app, path, &path["/projects/".len()..]),
+        (Method::Get, path) if path.starts_with("/blog/") => post_page(&ctx.app, path, &path["/blog/".len()..]),
+        _ => Page::not_found(url),
     };
 
-    let is_htmx = req.headers().iter().any(|h| h.field.equiv("HX-Request"));
-
-    let body = if is_htmx {
-        content.into_string()
+    let body = if ctx.htmx {
+        page.content.into_string()
     } else {
-        ui::render_full(title, content).into_string()
+        let layout = ui::Layout {
+            nonce: &ctx.nonce,
+            htmx_integrity: &ctx.app.htmx_integrity,
+            navbar: &ctx.app.ui.navbar,
+            site: &ctx.app.site,
+        };
+        ui::render_full(&page.title, page.content, &page.meta, &layout).into_string()
     };
 
-    let response = Response::from_string(body)
+    Response::from_string(body)
         .with_header(Header::from_str("Content-Type: text/html; charset=utf-8").unwrap())
-        .with_status_code(status);
+        .with_status_code(page.status)
+        .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use maud::html;
 
-    send_response(req, response)
+    use crate::{blog::Post, config::SiteConfig, markdown, models::parse_projects, ui::{Layout, PageMeta, components}};
+
+    use super::{Page, post_meta, project_meta};
+
+    fn head(title: &str, meta: &PageMeta) -> String {
+        let site = SiteConfig::default();
+        let layout = Layout { nonce: "n0nce", htmx_integrity: "sha384-x", navbar: &[], site: &site };
+        components::head(title, meta, &layout).into_string()
+    }
+
+    #[test]
+    fn home_head_uses_site_defaults() {
+        let page = Page::new("Home", "/home", html! {});
+        let head = head(&page.title, &page.meta);
+
+        assert!(head.contains(r#"<meta property="og:title" content="Home">"#));
+        assert!(head.contains(r#"<meta property="og:type" content="website">"#));
+        assert!(head.contains(r#"<link rel="canonical" href="https://mentaalachtergesteld.nl/home">"#));
+        assert!(head.contains(r#"<meta name="description" content="Personal website of MentaalAchtergesteld, with projects, a blog and a guestbook.">"#));
+        assert!(head.contains(r#"<meta name="twitter:image" content="https://mentaalachtergesteld.nl/static/img/personal-website.png">"#));
+        assert!(!head.contains("article:published_time"));
+    }
+
+    #[test]
+    fn project_head_describes_the_project() {
+        let projects = parse_projects(r#"
+            [[project]]
+            title = "Cats & Dogs"
+            description = "A <small> pet tracker"
+            source_url = "https://example.com/pets"
+            image_url = "/static/img/pets.png"
+            started = 2024-03-01
+        "#).unwrap();
+        let project = &projects[0];
+        let head = head(&project.title, &project_meta("/projects/cats-dogs", project));
+
+        assert!(head.contains(r#"<meta property="og:title" content="Cats &amp; Dogs">"#));
+        assert!(head.contains(r#"<meta name="twitter:description" content="A &lt;small&gt; pet tracker">"#));
+        assert!(head.contains(r#"<meta property="og:image" content="https://mentaalachtergesteld.nl/static/img/pets.png">"#));
+        assert!(head.contains(r#"<meta property="og:type" content="article">"#));
+        assert!(head.contains(r#"<meta property="article:published_time" content="2024-03-01">"#));
+    }
+
+    #[test]
+    fn post_head_carries_its_dates() {
+        let post = Post {
+            slug: "hello".into(),
+            title: "Hello \"world\"".into(),
+            date: NaiveDate::from_ymd_opt(2025, 1, 31).unwrap(),
+            updated: NaiveDate::from_ymd_opt(2025, 2, 14),
+            tags: Vec::new(),
+            draft: false,
+            summary: Some("First post".into()),
+            document: markdown::render("# Hi"),
+        };
+        let head = head(&post.title, &post_meta("/blog/hello", &post));
+
+        assert!(head.contains(r#"<meta name="twitter:title" content="Hello &quot;world&quot;">"#));
+        assert!(head.contains(r#"<meta property="og:description" content="First post">"#));
+        assert!(head.contains(r#"<meta property="og:url" content="https://mentaalachtergesteld.nl/blog/hello">"#));
+        assert!(head.contains(r#"<meta property="article:published_time" content="2025-01-31">"#));
+        assert!(head.contains(r#"<meta property="article:modified_time" content="2025-02-14">"#));
+    }
+
+    #[test]
+    fn not_found_head_stays_out_of_search_results() {
+        let page = Page::not_found("/nowhere");
+        let head = head(&page.title, &page.meta);
+
+        assert_eq!(page.status, 404);
+        assert!(head.contains(r#"<meta name="robots" content="noindex">"#));
+        assert!(!head.contains("canonical"));
+        assert!(!head.contains("og:"));
+    }
 }
@@ -0,0 +1,112 @@
+use chrono::{DateTime, Utc};
+use serde_json::{Value, json};
+
+use crate::{state::App, util::{LockExt, cache::CacheSummary}};
+
+pub fn liveness() -> Value {
+    json!({ "status": "ok" })
+}
+
+/// Checks the components the site can't work without. Upstream caches and
+/// circuits are only reported: Last.fm or wttr.in being down shouldn't get
+/// the container restarted.
+pub fn readiness(app: &App) -> (bool, Value) {
+    let database_ok = app.message_db.lock_recover().ping().is_ok();
+    let upstreams = json!({
+        "lastfm": app.lastfm.upstream().health(),
+        "wttr": app.wttr.upstream().health(),
+    });
+    let queue = json!({
+        "depth": app.pool_stats.queued(),
+        "capacity": app.pool_stats.capacity(),
+        "busy_workers": app.pool_stats.busy(),
+        "shed": app.pool_stats.shed(),
+    });
+
+    report(database_ok, app.projects.get().len(), &app.cache_summaries(), upstreams, queue, Utc::now())
+}
+
+fn report(
+    database_ok: bool,
+    project_count: usize,
+    caches: &[(&str, CacheSummary)],
+    upstreams: Value,
+    queue: Value,
+    now: DateTime<Utc>,
+) -> (bool, Value) {
+    let projects_ok = project_count > 0;
+
+    let caches: serde_json::Map<String, Value> = caches.iter()
+        .map(|(name, summary)| {
+            let status = match summary.refreshed_at {
+                Some(refreshed_at) => json!({
+                    "status": "ok",
+                    "last_refreshed": refreshed_at.to_rfc3339(),
+                    "age_seconds": (now - refreshed_at).num_seconds(),
+                }),
+                None => json!({ "status": "empty", "last_refreshed": null }),
+            };
+            (name.to_string(), status)
+        })
+        .collect();
+
+    let is_ready = database_ok && projects_ok;
+    let status = |ok: bool| if ok { "ok" } else { "error" };
+
+    let body = json!({
+        "status": status(is_ready),
+        "checks": {
+            "database": { "status": status(database_ok) },
+            "projects": { "status": status(projects_ok), "count": project_count },
+            "caches": caches,
+            "upstreams": upstreams,
+            "queue": queue,
+        }
+    });
+
+    (is_ready, body)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+    use serde_json::json;
+
+    use crate::util::cache::CacheSummary;
+
+    use super::report;
+
+    fn summary(refreshed_at: Option<DateTime<Utc>>) -> CacheSummary {
+        CacheSummary { hits: 0, misses: 0, stale: 0, evictions: 0, refreshed_at }
+    }
+
+    #[test]
+    fn ready_even_with_an_open_circuit() {
+        let now = Utc.with_ymd_and_hms(2025, 3, 1, 12, 0, 0).unwrap();
+        let caches = [("weather", summary(Some(now - Duration::seconds(90)))), ("lastfm_top_albums", summary(None))];
+        let upstreams = json!({ "lastfm": { "circuit": "open", "consecutive_failures": 5, "retry_in_seconds": 30 } });
+
+        let (is_ready, body) = report(true, 3, &caches, upstreams, json!({}), now);
+
+        assert!(is_ready);
+        assert_eq!(body["status"], "ok");
+        assert_eq!(body["checks"]["projects"], json!({ "status": "ok", "count": 3 }));
+        assert_eq!(body["checks"]["caches"]["weather"]["age_seconds"], 90);
+        assert_eq!(body["checks"]["caches"]["lastfm_top_albums"]["status"], "empty");
+        assert_eq!(body["checks"]["upstreams"]["lastfm"]["circuit"], "open");
+    }
+
+    #[test]
+    fn not_ready_without_database_or_projects() {
+        let now = Utc::now();
+
+        let (is_ready, body) = report(false, 3, &[], json!({}), json!({}), now);
+        assert!(!is_ready);
+        assert_eq!(body["status"], "error");
+        assert_eq!(body["checks"]["database"]["status"], "error");
+
+        let (is_ready, body) = report(true, 0, &[], json!({}), json!({}), now);
+        assert!(!is_ready);
+        assert_eq!(body["checks"]["projects"]["status"], "error");
+    }
+}
@@ -0,0 +1,407 @@
+use std::{sync::{Arc, OnceLock}, time::Duration};
+
+use sha2::{Digest, Sha256};
+
+use crate::util::{cache::CachePolicy, keyed_cache::KeyedCache};
+
+/// The least recently used highlighted blocks are dropped past this many.
+/// Pages only have a handful of code blocks each.
+const CACHE_LIMIT: usize = 512;
+/// Highlighting the same code always gives the same result, so entries only
+/// expire to let the memory go eventually.
+const CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(PartialEq, Eq)]
+enum Syntax {
+    Rust,
+    Lua,
+    C,
+    JavaScript,
+    Toml,
+}
+
+struct Language {
+    syntax: Syntax,
+    names: &'static [&'static str],
+    keywords: &'static [&'static str],
+    literals: &'static [&'static str],
+    types: &'static [&'static str],
+    line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static [char],
+    /// Identifiers starting with an uppercase letter are types.
+    capitalized_types: bool,
+}
+
+const RUST: Language = Language {
+    syntax: Syntax::Rust,
+    names: &["rust", "rs"],
+    keywords: &[
+        "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+        "extern", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut",
+        "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait", "type",
+        "unsafe", "use", "where", "while",
+    ],
+    literals: &["true", "false", "None", "Some", "Ok", "Err"],
+    types: &[
+        "bool", "char", "str", "u8", "u16", "u32", "u64", "u128", "usize",
+        "i8", "i16", "i32", "i64", "i128", "isize", "f32", "f64",
+    ],
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"'],
+    capitalized_types: true,
+};
+
+const LUA: Language = Language {
+    syntax: Syntax::Lua,
+    names: &["lua"],
+    keywords: &[
+        "and", "break", "do", "else", "elseif", "end", "for", "function", "goto", "if", "in",
+        "local", "not", "or", "repeat", "return", "then", "until", "while",
+    ],
+    literals: &["true", "false", "nil"],
+    types: &[],
+    line_comments: &["--"],
+    block_comment: Some(("--[[", "]]")),
+    quotes: &['"', '\''],
+    capitalized_types: false,
+};
+
+const C: Language = Language {
+    syntax: Syntax::C,
+    names: &["c", "h"],
+    keywords: &[
+        "break", "case", "const", "continue", "default", "do", "else", "enum", "extern", "for",
+        "goto", "if", "inline", "register", "restrict", "return", "sizeof", "static", "struct",
+        "switch", "typedef", "union", "volatile", "while",
+    ],
+    literals: &["true", "false", "NULL"],
+    types: &[
+        "void", "char", "short", "int", "long", "float", "double", "signed", "unsigned", "bool",
+        "size_t", "int8_t", "int16_t", "int32_t", "int64_t", "uint8_t", "uint16_t", "uint32_t",
+        "uint64_t",
+    ],
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\''],
+    capitalized_types: false,
+};
+
+const JAVASCRIPT: Language = Language {
+    syntax: Syntax::JavaScript,
+    names: &["javascript", "js", "mjs"],
+    keywords: &[
+        "async", "await", "break", "case", "catch", "class", "const", "continue", "default",
+        "delete", "do", "else", "export", "extends", "finally", "for", "from", "function", "if",
+        "import", "in", "instanceof", "let", "new", "of", "return", "static", "super", "switch",
+        "this", "throw", "try", "typeof", "var", "void", "while", "yield",
+    ],
+    literals: &["true", "false", "null", "undefined", "NaN", "Infinity"],
+    types: &[],
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\'', '`'],
+    capitalized_types: true,
+};
+
+const TOML: Language = Language {
+    syntax: Syntax::Toml,
+    names: &["toml"],
+    keywords: &[],
+    literals: &["true", "false", "inf", "nan"],
+    types: &[],
+    line_comments: &["#"],
+    block_comment: None,
+    quotes: &['"', '\''],
+    capitalized_types: false,
+};
+
+const LANGUAGES: [&Language; 5] = [&RUST, &LUA, &C, &JAVASCRIPT, &TOML];
+
+fn find_language(name: &str) -> Option<&'static Language> {
+    let name = name.trim().to_ascii_lowercase();
+    LANGUAGES.into_iter().find(|lang| lang.names.contains(&name.as_str()))
+}
+
+fn push_escaped(out: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+}
+
+fn push_span(out: &mut String, class: &str, text: &str) {
+    out.push_str("<span class=\"");
+    out.push_str(class);
+    out.push_str("\">");
+    push_escaped(out, text);
+    out.push_str("</span>");
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_'
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Byte length of the string literal at the start of `rest`, which starts
+/// with `quote`. Unterminated strings run to the end of the code.
+fn string_len(rest: &str, quote: char) -> usize {
+    let mut escaped = false;
+    for (i, c) in rest.char_indices().skip(1) {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == quote {
+            return i + c.len_utf8();
+        }
+    }
+    rest.len()
+}
+
+/// Rust uses `'` for both char literals and lifetimes. It's a char literal
+/// when it's closed right after one (possibly escaped) character.
+fn rust_char_len(rest: &str) -> Option<usize> {
+    let mut chars = rest.char_indices().skip(1);
+    let (_, first) = chars.next()?;
+    if first == '\\' {
+        let len = string_len(rest, '\'');
+        return (len <= 12).then_some(len);
+    }
+    let (i, closing) = chars.next()?;
+    (closing == '\'').then_some(i + 1)
+}
+
+fn number_len(rest: &str, lang: &Language) -> usize {
+    let toml = lang.syntax == Syntax::Toml;
+    let mut end = 0;
+    let mut chars = rest.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let next = chars.peek().map(|(_, c)| *c);
+        let keeps_going = c.is_alphanumeric() || c == '_'
+            // `1.5` but not the `..` of a range.
+            || (c == '.' && next.is_some_and(|n| n.is_ascii_digit()))
+            // Dates and times in TOML.
+            || (toml && matches!(c, '-' | ':' | '+') && next.is_some_and(|n| n.is_ascii_digit()));
+        if !keeps_going { break }
+        end = i + c.len_utf8();
+    }
+    end
+}
+
+fn highlight_with(code: &str, lang: &Language) -> String {
+    let mut out = String::with_capacity(code.len() * 2);
+    let mut i = 0;
+    let mut line_start = true;
+
+    while i < code.len() {
+        let rest = &code[i..];
+        let c = rest.chars().next().unwrap_or_default();
+
+        if let Some((open, close)) = lang.block_comment {
+            if let Some(body) = rest.strip_prefix(open) {
+                let len = body.find(close)
+                    .map_or(rest.len(), |end| open.len() + end + close.len());
+                push_span(&mut out, "hl-com", &rest[..len]);
+                i += len;
+                continue;
+            }
+        }
+
+        if lang.line_comments.iter().any(|start| rest.starts_with(start)) {
+            let len = rest.find('\n').unwrap_or(rest.len());
+            push_span(&mut out, "hl-com", &rest[..len]);
+            i += len;
+            continue;
+        }
+
+        // Preprocessor lines in C and attributes in Rust.
+        if c == '#' && ((line_start && lang.syntax == Syntax::C) || (lang.syntax == Syntax::Rust && rest[1..].trim_start_matches('!').starts_with('['))) {
+            let len = if lang.syntax == Syntax::C {
+                rest.find('\n').unwrap_or(rest.len())
+            } else {
+                rest.find(']').map_or(rest.len(), |end| end + 1)
+            };
+            push_span(&mut out, "hl-attr", &rest[..len]);
+            i += len;
+            line_start = false;
+            continue;
+        }
+
+        // TOML table headers.
+        if c == '[' && line_start && lang.syntax == Syntax::Toml {
+            let len = rest.find('\n').map_or(rest.len(), |end| rest[..end].trim_end().len());
+            push_span(&mut out, "hl-sec", &rest[..len]);
+            i += len;
+            line_start = false;
+            continue;
+        }
+
+        if c == '\'' && lang.syntax == Syntax::Rust {
+            let len = match rust_char_len(rest) {
+                Some(len) => {
+                    push_span(&mut out, "hl-str", &rest[..len]);
+                    len
+                }
+                None => {
+                    let len = 1 + rest[1..].find(|c: char| !is_ident_char(c)).unwrap_or(rest.len() - 1);
+                    push_span(&mut out, "hl-lit", &rest[..len]);
+                    len
+                }
+            };
+            i += len;
+            line_start = false;
+            continue;
+        }
+
+        if lang.quotes.contains(&c) {
+            let len = string_len(rest, c);
+            push_span(&mut out, "hl-str", &rest[..len]);
+            i += len;
+            line_start = false;
+            continue;
+        }
+
+        if c.is_ascii_digit() {
+            let len = number_len(rest, lang);
+            push_span(&mut out, "hl-num", &rest[..len]);
+            i += len;
+            line_start = false;
+            continue;
+        }
+
+        if is_ident_start(c) {
+            let len = rest.find(|c: char| !is_ident_char(c)).unwrap_or(rest.len());
+            let word = &rest[..len];
+            let after = rest[len..].trim_start_matches([' ', '\t']);
+
+            let class = if lang.syntax == Syntax::Toml && line_start && (after.starts_with('=') || after.starts_with('.')) {
+                Some("hl-key")
+            } else if lang.keywords.contains(&word) {
+                Some("hl-kw")
+            } else if lang.literals.contains(&word) {
+                Some("hl-lit")
+            } else if lang.types.contains(&word) || (lang.capitalized_types && word.starts_with(|c: char| c.is_uppercase())) {
+                Some("hl-type")
+            } else if after.starts_with('(') || (lang.syntax == Syntax::Rust && rest[len..].starts_with('!')) {
+                Some("hl-fn")
+            } else {
+                None
+            };
+
+            match class {
+                Some(class) => push_span(&mut out, class, word),
+                None => push_escaped(&mut out, word),
+            }
+            i += len;
+            line_start = false;
+            continue;
+        }
+
+        push_escaped(&mut out, &rest[..c.len_utf8()]);
+        i += c.len_utf8();
+        if c == '\n' {
+            line_start = true;
+        } else if !c.is_whitespace() {
+            line_start = false;
+        }
+    }
+
+    out
+}
+
+fn cache() -> &'static KeyedCache<[u8; 32], String> {
+    static CACHE: OnceLock<KeyedCache<[u8; 32], String>> = OnceLock::new();
+    CACHE.get_or_init(|| KeyedCache::new(CachePolicy::new(CACHE_TTL), CACHE_LIMIT))
+}
+
+/// Highlights `code` into `<span class="hl-*">` markup, or returns `None`
+/// for languages it doesn't know. Results are cached by a hash of the
+/// language and code.
+pub fn highlight(code: &str, language: &str) -> Option<Arc<String>> {
+    let lang = find_language(language)?;
+
+    let mut hasher = Sha256::new();
+    hasher.update(lang.names[0]);
+    hasher.update([0]);
+    hasher.update(code);
+    let key: [u8; 32] = hasher.finalize().into();
+
+    let code = code.to_string();
+    cache().get_or_update(key, move || Some(highlight_with(&code, lang)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_language, highlight, highlight_with};
+
+    fn render(code: &str, language: &str) -> String {
+        highlight_with(code, find_language(language).unwrap())
+    }
+
+    #[test]
+    fn tells_rust_lifetimes_from_char_literals() {
+        assert_eq!(
+            render("fn f<'a>(c: &'a str) -> char { 'x' }", "rust"),
+            "<span class=\"hl-kw\">fn</span> f&lt;<span class=\"hl-lit\">'a</span>&gt;(c: &amp;<span class=\"hl-lit\">'a</span> <span class=\"hl-type\">str</span>) -&gt; <span class=\"hl-type\">char</span> { <span class=\"hl-str\">'x'</span> }",
+        );
+        assert_eq!(render("'\\n'", "rust"), "<span class=\"hl-str\">'\\n'</span>");
+        assert_eq!(render("'static", "rust"), "<span class=\"hl-lit\">'static</span>");
+    }
+
+    #[test]
+    fn highlights_lua_block_comments() {
+        assert_eq!(
+            render("--[[ a\nb ]] x -- c", "lua"),
+            "<span class=\"hl-com\">--[[ a\nb ]]</span> x <span class=\"hl-com\">-- c</span>",
+        );
+    }
+
+    #[test]
+    fn highlights_c_preprocessor_lines() {
+        assert_eq!(
+            render("#include <stdio.h>\nint x = 1; // #not\n", "c"),
+            "<span class=\"hl-attr\">#include &lt;stdio.h&gt;</span>\n<span class=\"hl-type\">int</span> x = <span class=\"hl-num\">1</span>; <span class=\"hl-com\">// #not</span>\n",
+        );
+    }
+
+    #[test]
+    fn highlights_toml_keys_dates_and_tables() {
+        assert_eq!(
+            render("[package]\nname = \"site\"\ndate = 2024-03-01T12:00:00Z\nenabled = true", "toml"),
+            "<span class=\"hl-sec\">[package]</span>\n\
+             <span class=\"hl-key\">name</span> = <span class=\"hl-str\">&quot;site&quot;</span>\n\
+             <span class=\"hl-key\">date</span> = <span class=\"hl-num\">2024-03-01T12:00:00Z</span>\n\
+             <span class=\"hl-key\">enabled</span> = <span class=\"hl-lit\">true</span>",
+        );
+    }
+
+    #[test]
+    fn escapes_html() {
+        assert_eq!(
+            render("a < b && c > \"<script>\"", "js"),
+            "a &lt; b &amp;&amp; c &gt; <span class=\"hl-str\">&quot;&lt;script&gt;&quot;</span>",
+        );
+    }
+
+    #[test]
+    fn runs_unterminated_strings_and_comments_to_the_end() {
+        assert_eq!(render("let s = \"open\nx", "rust"), "<span class=\"hl-kw\">let</span> s = <span class=\"hl-str\">&quot;open\nx</span>");
+        assert_eq!(render("/* open", "c"), "<span class=\"hl-com\">/* open</span>");
+    }
+
+    #[test]
+    fn skips_unknown_languages() {
+        assert!(highlight("x", "brainfuck").is_none());
+        assert_eq!(highlight("nil", " Lua ").unwrap().as_str(), "<span class=\"hl-lit\">nil</span>");
+    }
+}
@@ -1,52 +1,278 @@
-use std::{env, sync::{Arc, Mutex}, time::Duration};
+use std::{any::Any, env, panic::{self, AssertUnwindSafe}, process::ExitCode, sync::{Arc, Mutex, PoisonError}, time::Duration};
 
 use dotenv::dotenv;
 use tiny_http::Server;
 
-use crate::{api::{lastfm::LastfmApi, wttr::WttrApi}, db::MessageDb, models::load_projects, state::{App, LastfmCache, WttrCache},  util::{rate_limiter::RateLimiter, threadpool::ThreadPool}};
+use crate::{access_log::AccessLog, api::{lastfm::LastfmApi, wttr::WttrApi}, blog::Blog, cli::{Command, DbAction, Overrides}, config::Config, db::MessageDb, handlers::RequestContext, metrics::Metrics, models::parse_projects, state::{App, LastfmCache, SiteText, WttrCache}, util::{rate_limiter::RateLimiter, reload::Reloadable, threadpool::ThreadPool}};
 
+mod access_log;
 mod db;
 mod api;
+mod assets;
+mod blog;
+mod cache_snapshot;
+mod cli;
+mod config;
 mod ui;
+mod feeds;
 mod handlers;
+mod health;
+mod highlight;
+mod markdown;
+mod metrics;
 mod models;
+#[cfg(feature = "og-image")]
+mod og_image;
+mod security;
 mod state;
 mod util;
 
-fn main() -> Result<(), ()> {
+const HTMX_PATH: &str = "script/htmx.min.js";
+const HTMX_SOURCE: &str = "https://cdn.jsdelivr.net/npm/htmx.org@2.0.6/dist/htmx.min.js";
+/// Exit codes, also listed in `cli::USAGE`.
+const EXIT_RUNTIME: u8 = 1;
+const EXIT_USAGE: u8 = 2;
+const EXIT_CONFIG: u8 = 3;
+
+fn main() -> ExitCode {
     dotenv().ok();
 
-    let address = env::var("SERVER_ADDRESS")
-        .map_err(|e| eprintln!("ERROR: Couldn't get server address: {e}"))?;
+    let command = match cli::parse(env::args().skip(1).collect()) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("ERROR: {e}\nRun `personal-website help` for usage.");
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+
+    match command {
+        Command::Help(usage) => {
+            println!("{usage}");
+            ExitCode::SUCCESS
+        }
+        Command::Version => {
+            println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+            ExitCode::SUCCESS
+        }
+        Command::CheckConfig(overrides) => match load_config(&overrides, true).and_then(check_config) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(()) => ExitCode::from(EXIT_CONFIG),
+        },
+        Command::Serve(overrides) => with_config(&overrides, true, serve),
+        Command::Db(action, overrides) => with_config(&overrides, false, |config| run_db(action, config)),
+    }
+}
+
+fn load_config(overrides: &Overrides, serving: bool) -> Result<Config, ()> {
+    let mut values = Vec::new();
+    if let Some(bind) = &overrides.bind {
+        values.push(("server.address", bind.clone()));
+    }
+    if let Some(dir) = &overrides.static_dir {
+        values.push(("server.static_dir", dir.display().to_string()));
+    }
+    if let Some(db) = &overrides.db {
+        values.push(("server.db_path", db.display().to_string()));
+    }
+
+    let config = Config::load(overrides.config.as_deref(), &values, serving)?;
+    assets::set_root(config.server.static_dir.clone());
+    Ok(config)
+}
+
+fn with_config(overrides: &Overrides, serving: bool, run: impl FnOnce(Config) -> Result<(), ()>) -> ExitCode {
+    let Ok(config) = load_config(overrides, serving) else {
+        return ExitCode::from(EXIT_CONFIG);
+    };
+
+    match run(config) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(()) => ExitCode::from(EXIT_RUNTIME),
+    }
+}
+
+fn check_config(config: Config) -> Result<(), ()> {
+    let projects = Reloadable::new("projects.toml", parse_projects)?;
+    for file in SiteText::FILES {
+        assets::read_text(file)
+            .ok_or_else(|| eprintln!("ERROR: Couldn't load `{}/{file}`", assets::root().display()))?;
+    }
+    for project in projects.get().iter() {
+        if let Some(page) = &project.page {
+            assets::read_text(page)
+                .ok_or_else(|| eprintln!("ERROR: Couldn't load page `{page}` of project `{}`", project.title))?;
+        }
+    }
+    assets::integrity(HTMX_PATH)
+        .ok_or_else(|| eprintln!("ERROR: Couldn't load `{}/{HTMX_PATH}`, download it from {HTMX_SOURCE}", assets::root().display()))?;
+
+    let blog = Blog::new(&config.blog)?;
+
+    println!("Configuration is valid");
+    println!("  address:    {}", config.server.address);
+    println!("  static dir: {}", config.server.static_dir.display());
+    println!("  database:   {}", config.server.db_path.display());
+    println!("  cache:      {}", config.snapshot_path().display());
+    println!("  projects:   {}", projects.get().len());
+    println!("  posts:      {} in {}", blog.posts().len(), config.blog.posts_dir.display());
+    Ok(())
+}
+
+fn run_db(action: DbAction, config: Config) -> Result<(), ()> {
+    let path = &config.server.db_path;
+    if !path.exists() {
+        eprintln!("ERROR: Database `{}` doesn't exist", path.display());
+        return Err(());
+    }
+    let db = MessageDb::new(path)?;
+
+    match action {
+        DbAction::Check => {
+            let problems = db.check()?;
+            for problem in &problems {
+                eprintln!("ERROR: {problem}");
+            }
+            if !problems.is_empty() {
+                return Err(());
+            }
+            println!("Database `{}` is ok", path.display());
+        }
+        DbAction::Vacuum => {
+            db.vacuum()?;
+            println!("Vacuumed `{}`", path.display());
+        }
+        DbAction::Backup(target) => {
+            db.backup(&target)?;
+            println!("Backed up `{}` to `{}`", path.display(), target.display());
+        }
+        DbAction::DeleteMessage(id) => {
+            if !db.delete_message(id)? {
+                eprintln!("ERROR: There is no message with id {id}");
+                return Err(());
+            }
+            println!("Deleted message {id}");
+        }
+    }
+
+    db.close()
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload.downcast_ref::<&str>().copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
+
+/// Closes the guestbook database, which only works once every request that
+/// could still hold a reference to the app has finished.
+fn close_database(app: Arc<App>) -> Result<(), ()> {
+    let app = Arc::try_unwrap(app)
+        .map_err(|_| eprintln!("ERROR: Requests still hold the app, not closing the database"))?;
+    let db = Arc::try_unwrap(app.message_db)
+        .map_err(|_| eprintln!("ERROR: Database is still in use, not closing it"))?
+        .into_inner()
+        .unwrap_or_else(PoisonError::into_inner);
+
+    db.close()
+}
+
+fn serve(config: Config) -> Result<(), ()> {
+    let snapshot_path = config.snapshot_path();
+    let address = config.server.address;
     let server = Server::http(&address)
         .map_err(|e| eprintln!("ERROR: Couldn't start server: {e}"))?;
 
-    let lastfm_key = env::var("LASTFM_KEY")
-        .map_err(|e| eprintln!("ERROR: Couldn't get lastfm key: {e}"))?;
+    let shutdown_timeout = Duration::from_secs(config.server.shutdown_timeout_secs);
+    let retry_after = config.server.retry_after_secs;
+
+    let htmx_integrity = assets::integrity(HTMX_PATH)
+        .ok_or_else(|| eprintln!("ERROR: Couldn't load `{}/{HTMX_PATH}`, download it from {HTMX_SOURCE}", assets::root().display()))?;
+
+    let pool = ThreadPool::new(config.server.threads, config.server.queue_capacity);
+
+    // `Config::load` refuses to return without a key.
+    let lastfm_key = config.lastfm.api_key.unwrap_or_default();
+
+    let wttr_cache = WttrCache::new(&config.cache);
+    let lastfm_cache = LastfmCache::new(&config.cache);
+    cache_snapshot::load(&snapshot_path, &lastfm_cache, &wttr_cache);
+    if config.cache.snapshot_interval_secs > 0 {
+        let interval = Duration::from_secs(config.cache.snapshot_interval_secs);
+        cache_snapshot::spawn_periodic(snapshot_path.clone(), interval, lastfm_cache.clone(), wttr_cache.clone());
+    }
 
     let app = Arc::new(App {
-        wttr: WttrApi::new(),
-        lastfm: LastfmApi::new(lastfm_key, "gravitowl".into()),
+        wttr: Arc::new(WttrApi::new(&config.weather.location, &config.upstream)),
+        lastfm: Arc::new(LastfmApi::new(lastfm_key, config.lastfm.username, &config.upstream)),
 
-        wttr_cache: WttrCache::new(),
-        lastfm_cache: LastfmCache::new(),
+        wttr_cache,
+        lastfm_cache,
 
-        projects: load_projects("static/projects.toml")?,
-        message_db: Arc::new(Mutex::new(MessageDb::new("guestbook.db")?)),
-        rate_limiter: Arc::new(Mutex::new(RateLimiter::new(Duration::from_secs(10)))),
+        projects: Reloadable::new("projects.toml", parse_projects)?,
+        text: SiteText::load(),
+        blog: Blog::new(&config.blog)?,
+        message_db: Arc::new(Mutex::new(MessageDb::new(&config.server.db_path)?)),
+        rate_limiter: Arc::new(Mutex::new(RateLimiter::new(Duration::from_secs(config.rate_limit.cooldown_secs)))),
+        security: config.security,
+        htmx_integrity,
+        access_log: AccessLog::new(config.access_log)?,
+        metrics: Metrics::new(config.metrics.allow),
+        pool_stats: pool.stats(),
+        ui: config.ui,
+        site: config.site,
     });
 
-    println!("Server listening on address {address}");
+    let embedded = assets::embedded_count();
+    if embedded > 0 {
+        println!("Serving {embedded} embedded static files as fallback");
+    }
+
+    // The handler only holds a weak reference, so dropping the server after
+    // the loop really closes the listening socket.
+    let server = Arc::new(server);
+    let signal_server = Arc::downgrade(&server);
+    ctrlc::set_handler(move || {
+        if let Some(server) = signal_server.upgrade() {
+            println!("Received shutdown signal, no longer accepting connections");
+            server.unblock();
+        }
+    }).map_err(|e| eprintln!("ERROR: Couldn't install signal handler: {e}"))?;
 
-    let pool = ThreadPool::new(16);
+    println!("Server listening on address {address}");
 
     for request in server.incoming_requests() {
-        let app = Arc::clone(&app);
+        let ctx = RequestContext::new(&request, Arc::clone(&app));
+
+        let queued = pool.try_execute((request, ctx), |(mut request, ctx)| {
+            let response = panic::catch_unwind(AssertUnwindSafe(|| handlers::handle_request(&mut request, &ctx)))
+                .unwrap_or_else(|payload| {
+                    eprintln!("ERROR: Request {} panicked: {}", ctx.request_id, panic_message(&*payload));
+                    handlers::internal_error()
+                });
 
-        pool.execute(move || {
-            let _ = handlers::handle_request(request, app)
-                .map_err(|_| eprintln!("ERROR: Couldn't handle request."));
+            if handlers::send_response(&ctx, request, response).is_err() {
+                eprintln!("ERROR: Couldn't handle request {}.", ctx.request_id);
+            }
         });
+
+        if let Err((request, ctx)) = queued {
+            let _ = handlers::handle_overload(request, &ctx, retry_after);
+        }
     }
-    Ok(())  
+
+    drop(server);
+
+    let pending = app.pool_stats.queued() + app.pool_stats.busy();
+    println!("Waiting up to {}s for {pending} pending requests", shutdown_timeout.as_secs());
+
+    let drained = pool.shutdown(shutdown_timeout);
+    if !drained {
+        eprintln!("ERROR: Requests still running after {}s, exiting anyway", shutdown_timeout.as_secs());
+    }
+
+    let saved = cache_snapshot::save(&snapshot_path, &app.lastfm_cache, &app.wttr_cache);
+    let closed = close_database(app);
+    println!("Shutdown complete");
+
+    if drained && saved.is_ok() && closed.is_ok() { Ok(()) } else { Err(()) }
 }
@@ -0,0 +1,147 @@
+use std::collections::HashSet;
+
+use pulldown_cmark::{CodeBlockKind, CowStr, Event, HeadingLevel, Options, Parser, Tag, TagEnd, html};
+
+use crate::{highlight, util::slugify};
+
+pub struct TocEntry {
+    pub level: u8,
+    pub id: String,
+    pub text: String,
+}
+
+/// Markdown rendered to HTML, with the headings for a table of contents.
+pub struct Document {
+    pub html: String,
+    pub toc: Vec<TocEntry>,
+}
+
+fn heading_level(level: HeadingLevel) -> u8 {
+    match level {
+        HeadingLevel::H1 => 1,
+        HeadingLevel::H2 => 2,
+        HeadingLevel::H3 => 3,
+        HeadingLevel::H4 => 4,
+        HeadingLevel::H5 => 5,
+        HeadingLevel::H6 => 6,
+    }
+}
+
+/// Replaces fenced code blocks in a language `highlight` knows with
+/// highlighted HTML. Other code blocks are left for pulldown-cmark.
+fn highlight_code_blocks(events: Vec<Event>) -> Vec<Event> {
+    let mut output = Vec::with_capacity(events.len());
+    let mut events = events.into_iter();
+
+    while let Some(event) = events.next() {
+        let Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) = &event else {
+            output.push(event);
+            continue;
+        };
+        // The info string can carry more than the language, like `rust,ignore`.
+        let language = info.split([',', ' ']).next().unwrap_or_default().to_string();
+
+        let mut block = vec![event];
+        let mut code = String::new();
+        for inner in events.by_ref() {
+            let end = matches!(inner, Event::End(TagEnd::CodeBlock));
+            if let Event::Text(text) = &inner {
+                code.push_str(text);
+            }
+            block.push(inner);
+            if end { break }
+        }
+
+        match highlight::highlight(&code, &language) {
+            Some(highlighted) => output.push(Event::Html(CowStr::from(format!(
+                "<pre><code class=\"language-{language} hl\">{highlighted}</code></pre>\n"
+            )))),
+            None => output.extend(block),
+        }
+    }
+
+    output
+}
+
+/// Renders `source`, giving every heading an id so the table of contents
+/// can link to it. Ids come from `{#custom-id}` when given and from the
+/// heading text otherwise, with a number appended to duplicates.
+pub fn render(source: &str) -> Document {
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_HEADING_ATTRIBUTES;
+
+    let mut events: Vec<Event> = Parser::new_ext(source, options).collect();
+    let mut toc = Vec::new();
+    let mut used_ids = HashSet::new();
+
+    let mut index = 0;
+    while index < events.len() {
+        let Event::Start(Tag::Heading { level, id, .. }) = &events[index] else {
+            index += 1;
+            continue;
+        };
+        let level = *level;
+        let explicit_id = id.as_ref().map(|id| id.to_string());
+
+        let mut text = String::new();
+        for event in &events[index + 1..] {
+            match event {
+                Event::End(TagEnd::Heading(_)) => break,
+                Event::Text(t) | Event::Code(t) => text.push_str(t),
+                _ => {}
+            }
+        }
+
+        let base = explicit_id.unwrap_or_else(|| slugify(&text));
+        let base = if base.is_empty() { "section".to_string() } else { base };
+        let mut unique = base.clone();
+        let mut suffix = 2;
+        while !used_ids.insert(unique.clone()) {
+            unique = format!("{base}-{suffix}");
+            suffix += 1;
+        }
+
+        if let Event::Start(Tag::Heading { id, .. }) = &mut events[index] {
+            *id = Some(CowStr::from(unique.clone()));
+        }
+        toc.push(TocEntry { level: heading_level(level), id: unique, text });
+        index += 1;
+    }
+
+    let events = highlight_code_blocks(events);
+
+    let mut output = String::with_capacity(source.len() * 3 / 2);
+    html::push_html(&mut output, events.into_iter());
+
+    Document { html: output, toc }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render;
+
+    fn ids(source: &str) -> Vec<String> {
+        render(source).toc.into_iter().map(|entry| entry.id).collect()
+    }
+
+    #[test]
+    fn numbers_duplicate_heading_ids() {
+        assert_eq!(ids("# Setup\n## Setup\n### Setup\n"), ["setup", "setup-2", "setup-3"]);
+        assert_eq!(ids("# !!!\n# ???\n"), ["section", "section-2"]);
+    }
+
+    #[test]
+    fn uses_custom_heading_ids() {
+        let document = render("# Getting started {#start}\n## Start\n## Other `code`\n");
+        let toc: Vec<(u8, &str, &str)> = document.toc.iter()
+            .map(|entry| (entry.level, entry.id.as_str(), entry.text.as_str()))
+            .collect();
+
+        assert_eq!(toc, [(1, "start", "Getting started"), (2, "start-2", "Start"), (2, "other-code", "Other code")]);
+        assert!(document.html.contains("<h1 id=\"start\">Getting started</h1>"));
+        assert!(document.html.contains("<h2 id=\"start-2\">Start</h2>"));
+    }
+}
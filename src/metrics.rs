@@ -0,0 +1,237 @@
+use std::{collections::HashMap, fmt::Write, net::IpAddr, sync::{Mutex, atomic::{AtomicU64, Ordering}}, time::Duration};
+
+use crate::{state::App, util::LockExt};
+
+const LATENCY_BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Known routes, used as the `route` label so arbitrary paths can't blow up
+/// the number of series.
+const ROUTES: [&str; 21] = [
+    "/", "/home", "/guestbook", "/projects", "/blog", "/interests", "/metrics", "/healthz", "/readyz",
+    "/robots.txt", "/sitemap.xml", "/feed.atom",
+    "/comp/lastfm-stats", "/comp/now-playing", "/comp/top-artists", "/comp/top-tracks", "/comp/top-albums",
+    "/comp/user-stats", "/comp/server-weather", "/comp/projects", "/comp/messages",
+];
+
+pub fn route_label(url: &str) -> &'static str {
+    let path = url.split("?").next().unwrap_or("");
+    if path.starts_with("/static/") { return "/static/*" }
+    if path.starts_with("/projects/") { return "/projects/*" }
+    if path.starts_with("/blog/") { return "/blog/*" }
+    if path.starts_with("/og/") { return "/og/*" }
+
+    ROUTES.iter().find(|route| **route == path).copied().unwrap_or("other")
+}
+
+pub struct Histogram {
+    buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            buckets: Default::default(),
+            count: AtomicU64::new(0),
+            sum_micros: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Histogram {
+    pub fn observe(&self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        for (bucket, le) in self.buckets.iter().zip(LATENCY_BUCKETS) {
+            if seconds <= le { bucket.fetch_add(1, Ordering::Relaxed); }
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros.fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let sep = if labels.is_empty() { "" } else { "," };
+        for (bucket, le) in self.buckets.iter().zip(LATENCY_BUCKETS) {
+            let _ = writeln!(out, "{name}_bucket{{{labels}{sep}le=\"{le}\"}} {}", bucket.load(Ordering::Relaxed));
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        let sum = self.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+        let _ = writeln!(out, "{name}_bucket{{{labels}{sep}le=\"+Inf\"}} {count}");
+        let _ = writeln!(out, "{name}_sum{{{labels}}} {sum}");
+        let _ = writeln!(out, "{name}_count{{{labels}}} {count}");
+    }
+}
+
+#[derive(Default)]
+pub struct UpstreamStats {
+    latency: Histogram,
+    errors: AtomicU64,
+}
+
+impl UpstreamStats {
+    pub fn record(&self, duration: Duration, is_ok: bool) {
+        self.latency.observe(duration);
+        if !is_ok { self.errors.fetch_add(1, Ordering::Relaxed); }
+    }
+}
+
+pub struct Metrics {
+    pub allowed: Vec<IpAddr>,
+    requests: Mutex<HashMap<(&'static str, u16), Histogram>>,
+    guestbook_posts: AtomicU64,
+}
+
+impl Metrics {
+    pub fn new(allowed: Vec<IpAddr>) -> Self {
+        Self { allowed, requests: Mutex::new(HashMap::new()), guestbook_posts: AtomicU64::new(0) }
+    }
+
+    pub fn observe_request(&self, route: &'static str, status: u16, latency: Duration) {
+        self.requests.lock_recover()
+            .entry((route, status))
+            .or_default()
+            .observe(latency);
+    }
+
+    pub fn inc_guestbook_posts(&self) {
+        self.guestbook_posts.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+fn render_requests(out: &mut String, metrics: &Metrics) {
+    let requests = metrics.requests.lock_recover();
+    let mut keys: Vec<_> = requests.keys().copied().collect();
+    keys.sort();
+
+    header(out, "site_http_requests_total", "counter", "HTTP requests by route and status.");
+    for (route, status) in &keys {
+        let count = requests[&(*route, *status)].count.load(Ordering::Relaxed);
+        let _ = writeln!(out, "site_http_requests_total{{route=\"{route}\",status=\"{status}\"}} {count}");
+    }
+
+    header(out, "site_http_request_duration_seconds", "histogram", "HTTP request latency by route and status.");
+    for (route, status) in &keys {
+        let labels = format!("route=\"{route}\",status=\"{status}\"");
+        requests[&(*route, *status)].render(out, "site_http_request_duration_seconds", &labels);
+    }
+}
+
+pub fn render(app: &App) -> String {
+    let mut out = String::new();
+    render_requests(&mut out, &app.metrics);
+
+    let pool = &app.pool_stats;
+    header(&mut out, "site_threadpool_workers", "gauge", "Number of worker threads.");
+    let _ = writeln!(out, "site_threadpool_workers {}", pool.size());
+    header(&mut out, "site_threadpool_queue_depth", "gauge", "Jobs waiting for a worker.");
+    let _ = writeln!(out, "site_threadpool_queue_depth {}", pool.queued());
+    header(&mut out, "site_threadpool_queue_capacity", "gauge", "Maximum number of jobs waiting for a worker.");
+    let _ = writeln!(out, "site_threadpool_queue_capacity {}", pool.capacity());
+    header(&mut out, "site_threadpool_shed_total", "counter", "Requests answered with 503 because the queue was full.");
+    let _ = writeln!(out, "site_threadpool_shed_total {}", pool.shed());
+    header(&mut out, "site_threadpool_busy_workers", "gauge", "Workers currently running a job.");
+    let _ = writeln!(out, "site_threadpool_busy_workers {}", pool.busy());
+    header(&mut out, "site_threadpool_respawned_workers_total", "counter", "Worker threads that died and were replaced.");
+    let _ = writeln!(out, "site_threadpool_respawned_workers_total {}", pool.respawned());
+
+    let caches = app.cache_summaries();
+    header(&mut out, "site_cache_hits_total", "counter", "Cache lookups served from a valid entry.");
+    for (name, summary) in &caches {
+        let _ = writeln!(out, "site_cache_hits_total{{cache=\"{name}\"}} {}", summary.hits);
+    }
+    header(&mut out, "site_cache_misses_total", "counter", "Cache lookups that had to call the fetcher.");
+    for (name, summary) in &caches {
+        let _ = writeln!(out, "site_cache_misses_total{{cache=\"{name}\"}} {}", summary.misses);
+    }
+    header(&mut out, "site_cache_stale_total", "counter", "Cache lookups served an expired value while it refreshed.");
+    for (name, summary) in &caches {
+        let _ = writeln!(out, "site_cache_stale_total{{cache=\"{name}\"}} {}", summary.stale);
+    }
+    header(&mut out, "site_cache_evictions_total", "counter", "Cache entries dropped to make room for new ones.");
+    for (name, summary) in &caches {
+        let _ = writeln!(out, "site_cache_evictions_total{{cache=\"{name}\"}} {}", summary.evictions);
+    }
+
+    let upstreams = [("lastfm", app.lastfm.upstream().stats()), ("wttr", app.wttr.upstream().stats())];
+    header(&mut out, "site_upstream_request_duration_seconds", "histogram", "Latency of calls to upstream APIs.");
+    for (name, stats) in &upstreams {
+        stats.latency.render(&mut out, "site_upstream_request_duration_seconds", &format!("upstream=\"{name}\""));
+    }
+    header(&mut out, "site_upstream_errors_total", "counter", "Failed calls to upstream APIs.");
+    for (name, stats) in &upstreams {
+        let _ = writeln!(out, "site_upstream_errors_total{{upstream=\"{name}\"}} {}", stats.errors.load(Ordering::Relaxed));
+    }
+
+    header(&mut out, "site_rate_limiter_rejections_total", "counter", "Guestbook posts rejected by the rate limiter.");
+    let _ = writeln!(out, "site_rate_limiter_rejections_total {}", app.rate_limiter.lock_recover().rejections());
+    header(&mut out, "site_guestbook_posts_total", "counter", "Guestbook messages created.");
+    let _ = writeln!(out, "site_guestbook_posts_total {}", app.metrics.guestbook_posts.load(Ordering::Relaxed));
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::IpAddr, time::Duration};
+
+    use super::{Histogram, Metrics, render_requests, route_label};
+
+    #[test]
+    fn labels_known_routes_only() {
+        assert_eq!(route_label("/metrics"), "/metrics");
+        assert_eq!(route_label("/projects?tag=rust"), "/projects");
+        assert_eq!(route_label("/static/style/styles.css?v=2"), "/static/*");
+        assert_eq!(route_label("/projects/personal-website"), "/projects/*");
+        assert_eq!(route_label("/blog/hello-world"), "/blog/*");
+        assert_eq!(route_label("/og/blog/hello-world.png"), "/og/*");
+        assert_eq!(route_label("/wp-login.php"), "other");
+        assert_eq!(route_label("/comp/unknown"), "other");
+    }
+
+    #[test]
+    fn renders_cumulative_histogram() {
+        let histogram = Histogram::default();
+        histogram.observe(Duration::from_millis(3));
+        histogram.observe(Duration::from_millis(200));
+        histogram.observe(Duration::from_secs(30));
+
+        let mut out = String::new();
+        histogram.render(&mut out, "latency", "upstream=\"lastfm\"");
+        let lines: Vec<&str> = out.lines().collect();
+
+        assert_eq!(lines[0], "latency_bucket{upstream=\"lastfm\",le=\"0.005\"} 1");
+        assert_eq!(lines[5], "latency_bucket{upstream=\"lastfm\",le=\"0.25\"} 2");
+        assert_eq!(lines[10], "latency_bucket{upstream=\"lastfm\",le=\"10\"} 2");
+        assert_eq!(lines[11], "latency_bucket{upstream=\"lastfm\",le=\"+Inf\"} 3");
+        assert_eq!(lines[12], "latency_sum{upstream=\"lastfm\"} 30.203");
+        assert_eq!(lines[13], "latency_count{upstream=\"lastfm\"} 3");
+    }
+
+    #[test]
+    fn renders_requests_sorted_by_route_and_status() {
+        let metrics = Metrics::new(vec![IpAddr::from([127, 0, 0, 1])]);
+        metrics.observe_request("/projects", 200, Duration::from_millis(20));
+        metrics.observe_request("/blog", 404, Duration::from_millis(1));
+        metrics.observe_request("/blog", 200, Duration::from_millis(2));
+        metrics.observe_request("/blog", 200, Duration::from_millis(4));
+
+        let mut out = String::new();
+        render_requests(&mut out, &metrics);
+
+        let totals: Vec<&str> = out.lines().filter(|line| line.starts_with("site_http_requests_total{")).collect();
+        assert_eq!(totals, [
+            "site_http_requests_total{route=\"/blog\",status=\"200\"} 2",
+            "site_http_requests_total{route=\"/blog\",status=\"404\"} 1",
+            "site_http_requests_total{route=\"/projects\",status=\"200\"} 1",
+        ]);
+
+        assert!(out.starts_with("# HELP site_http_requests_total HTTP requests by route and status.\n# TYPE site_http_requests_total counter\n"));
+        assert!(out.contains("# TYPE site_http_request_duration_seconds histogram\n"));
+        assert!(out.contains("site_http_request_duration_seconds_count{route=\"/blog\",status=\"200\"} 2\n"));
+    }
+}
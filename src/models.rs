@@ -1,7 +1,43 @@
-use std::{fs, path::Path};
+use std::{cmp::Reverse, collections::{BTreeSet, HashMap, HashSet}};
 
 use chrono::{DateTime, Utc};
 use serde::Deserialize;
+use toml::value::Datetime;
+use url::form_urlencoded;
+
+use crate::{assets, markdown::{self, Document}, util::slugify};
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum ProjectStatus {
+    Active,
+    Wip,
+    Archived,
+}
+
+impl ProjectStatus {
+    pub const ALL: [ProjectStatus; 3] = [Self::Active, Self::Wip, Self::Archived];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Active   => "active",
+            Self::Wip      => "wip",
+            Self::Archived => "archived",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Active   => "Active",
+            Self::Wip      => "Work in progress",
+            Self::Archived => "Archived",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|status| status.as_str() == value)
+    }
+}
 
 #[derive(Deserialize)]
 pub struct Project {
@@ -9,7 +45,134 @@ pub struct Project {
     pub description: String,
     pub source_url: String,
     pub deploy_url: Option<String>,
-    pub image_url: Option<String>
+    pub image_url: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub languages: Vec<String>,
+    pub status: Option<ProjectStatus>,
+    /// A TOML date like `2024-03-01`, only the date part is used.
+    pub started: Option<Datetime>,
+    #[serde(default)]
+    pub featured: bool,
+    /// Used in `/projects/{slug}`, generated from the title when empty.
+    #[serde(default)]
+    pub slug: String,
+    /// Markdown file under the static root shown on the project's page.
+    pub page: Option<String>,
+    /// `page` rendered when the projects are loaded, so it's reloaded along
+    /// with `projects.toml` rather than on every request.
+    #[serde(skip)]
+    pub document: Option<Document>,
+}
+
+impl Project {
+    /// `(year, month, day)` of `started`, for sorting.
+    pub fn started_on(&self) -> Option<(u16, u8, u8)> {
+        let date = self.started.as_ref()?.date?;
+        Some((date.year, date.month, date.day))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProjectSort {
+    #[default]
+    Featured,
+    Newest,
+    Alphabetical,
+}
+
+impl ProjectSort {
+    pub const ALL: [ProjectSort; 3] = [Self::Featured, Self::Newest, Self::Alphabetical];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Featured     => "featured",
+            Self::Newest       => "newest",
+            Self::Alphabetical => "alphabetical",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Featured     => "Featured first",
+            Self::Newest       => "Newest",
+            Self::Alphabetical => "A-Z",
+        }
+    }
+}
+
+/// Filter and sort options for the projects list, read from and written
+/// back to the query string so pagination keeps them.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct ProjectQuery {
+    pub tag: Option<String>,
+    pub language: Option<String>,
+    pub status: Option<ProjectStatus>,
+    pub sort: ProjectSort,
+}
+
+impl ProjectQuery {
+    pub fn from_params(params: &HashMap<String, String>) -> Self {
+        let non_empty = |key: &str| params.get(key).filter(|v| !v.is_empty()).cloned();
+
+        Self {
+            tag: non_empty("tag"),
+            language: non_empty("language"),
+            status: params.get("status").and_then(|v| ProjectStatus::parse(v)),
+            sort: params.get("sort")
+                .and_then(|v| ProjectSort::ALL.into_iter().find(|sort| sort.as_str() == v))
+                .unwrap_or_default(),
+        }
+    }
+
+    /// The query string for these options, without the leading `?`.
+    pub fn to_query_string(&self) -> String {
+        let mut query = form_urlencoded::Serializer::new(String::new());
+        if let Some(tag) = &self.tag { query.append_pair("tag", tag); }
+        if let Some(language) = &self.language { query.append_pair("language", language); }
+        if let Some(status) = self.status { query.append_pair("status", status.as_str()); }
+        if self.sort != ProjectSort::default() { query.append_pair("sort", self.sort.as_str()); }
+        query.finish()
+    }
+
+    pub fn matches(&self, project: &Project) -> bool {
+        self.tag.as_ref().is_none_or(|tag| project.tags.contains(tag))
+            && self.language.as_ref().is_none_or(|language| project.languages.contains(language))
+            && self.status.is_none_or(|status| project.status == Some(status))
+    }
+
+    /// The matching projects in display order. Sorting is stable, so ties
+    /// keep the order of `projects.toml`.
+    pub fn apply<'a>(&self, projects: &'a [Project]) -> Vec<&'a Project> {
+        let mut matching: Vec<&Project> = projects.iter().filter(|p| self.matches(p)).collect();
+
+        match self.sort {
+            ProjectSort::Featured => matching.sort_by_key(|p| !p.featured),
+            // Projects without a start date go last.
+            ProjectSort::Newest => matching.sort_by_key(|p| (p.started_on().is_none(), Reverse(p.started_on()))),
+            ProjectSort::Alphabetical => matching.sort_by_key(|p| p.title.to_lowercase()),
+        }
+        matching
+    }
+}
+
+/// Every tag, language and status used by at least one project, for the
+/// filter chips.
+pub struct ProjectFacets {
+    pub tags: BTreeSet<String>,
+    pub languages: BTreeSet<String>,
+    pub statuses: BTreeSet<ProjectStatus>,
+}
+
+impl ProjectFacets {
+    pub fn collect(projects: &[Project]) -> Self {
+        Self {
+            tags: projects.iter().flat_map(|p| p.tags.iter().cloned()).collect(),
+            languages: projects.iter().flat_map(|p| p.languages.iter().cloned()).collect(),
+            statuses: projects.iter().filter_map(|p| p.status).collect(),
+        }
+    }
 }
 
 #[derive(Deserialize)]
@@ -17,12 +180,31 @@ pub struct ProjectsFile {
     project: Vec<Project>,
 }
 
-pub fn load_projects<P: AsRef<Path>>(path: P) -> Result<Vec<Project>, ()> {
-    let file_content = fs::read_to_string(path)
-        .map_err(|e| eprintln!("ERROR: Couldn't load projects: {e}"))?;
-    let data: ProjectsFile = toml::from_str(&file_content)
+pub fn parse_projects(content: &str) -> Result<Vec<Project>, ()> {
+    let mut data: ProjectsFile = toml::from_str(content)
         .map_err(|e| eprintln!("ERROR: Couldn't parse projects: {e}"))?;
 
+    let mut slugs = HashSet::new();
+    for project in &mut data.project {
+        project.document = project.page.as_deref().and_then(|page| {
+            let source = assets::read_text(page)
+                .or_else(|| { eprintln!("ERROR: Couldn't load page `{page}` of project `{}`", project.title); None })?;
+            Some(markdown::render(&source))
+        });
+
+        if project.slug.is_empty() {
+            project.slug = slugify(&project.title);
+        }
+        if project.slug.is_empty() {
+            eprintln!("ERROR: Project `{}` needs a `slug`, its title has no letters or digits", project.title);
+            return Err(());
+        }
+        if !slugs.insert(project.slug.clone()) {
+            eprintln!("ERROR: Projects share the slug `{}`, set a different `slug` on one of them", project.slug);
+            return Err(());
+        }
+    }
+
     Ok(data.project)
 }
 
@@ -33,3 +215,114 @@ pub struct Message {
     pub content: String,
     pub timestamp: DateTime<Utc>
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use url::form_urlencoded;
+
+    use super::{Project, ProjectQuery, ProjectSort, ProjectStatus, parse_projects};
+
+    fn projects() -> Vec<Project> {
+        parse_projects(r#"
+            [[project]]
+            title = "website"
+            description = ""
+            source_url = ""
+            tags = ["web"]
+            languages = ["Rust", "HTML"]
+            status = "active"
+            started = 2024-03-01
+
+            [[project]]
+            title = "Bot"
+            description = ""
+            source_url = ""
+            tags = ["discord"]
+            languages = ["Rust"]
+            status = "archived"
+            started = 2022-06-10
+            featured = true
+
+            [[project]]
+            title = "Game"
+            description = ""
+            source_url = ""
+            tags = ["web", "game"]
+            languages = ["Lua"]
+            status = "wip"
+
+            [[project]]
+            title = "app"
+            description = ""
+            source_url = ""
+            languages = ["Rust"]
+            started = 2025-01-15
+            featured = true
+        "#).unwrap()
+    }
+
+    fn params(query: &str) -> HashMap<String, String> {
+        form_urlencoded::parse(query.as_bytes()).into_owned().collect()
+    }
+
+    fn titles(query: &ProjectQuery, projects: &[Project]) -> Vec<String> {
+        query.apply(projects).iter().map(|p| p.title.clone()).collect()
+    }
+
+    #[test]
+    fn reads_params_and_ignores_unknown_values() {
+        let query = ProjectQuery::from_params(&params("tag=web&language=Rust&status=wip&sort=newest"));
+        assert_eq!(query.tag.as_deref(), Some("web"));
+        assert_eq!(query.language.as_deref(), Some("Rust"));
+        assert!(query.status == Some(ProjectStatus::Wip));
+        assert!(query.sort == ProjectSort::Newest);
+
+        let query = ProjectQuery::from_params(&params("tag=&status=done&sort=random&page=2"));
+        assert!(query == ProjectQuery::default());
+    }
+
+    #[test]
+    fn combines_filters() {
+        let projects = projects();
+        let query = |query: &str| ProjectQuery::from_params(&params(query));
+
+        assert_eq!(titles(&query("tag=web"), &projects), ["website", "Game"]);
+        assert_eq!(titles(&query("language=Rust"), &projects), ["Bot", "app", "website"]);
+        assert_eq!(titles(&query("tag=web&language=Rust"), &projects), ["website"]);
+        assert_eq!(titles(&query("language=Rust&status=archived"), &projects), ["Bot"]);
+        assert!(titles(&query("tag=game&status=active"), &projects).is_empty());
+    }
+
+    #[test]
+    fn sorts_in_every_mode() {
+        let projects = projects();
+        let sorted = |sort| titles(&ProjectQuery { sort, ..ProjectQuery::default() }, &projects);
+
+        // Ties keep the order of the file.
+        assert_eq!(sorted(ProjectSort::Featured), ["Bot", "app", "website", "Game"]);
+        assert_eq!(sorted(ProjectSort::Newest), ["app", "website", "Bot", "Game"]);
+        assert_eq!(sorted(ProjectSort::Alphabetical), ["app", "Bot", "Game", "website"]);
+    }
+
+    #[test]
+    fn round_trips_through_the_query_string() {
+        assert_eq!(ProjectQuery::default().to_query_string(), "");
+
+        let queries = [
+            ProjectQuery { tag: Some("c++ & more".into()), ..ProjectQuery::default() },
+            ProjectQuery { language: Some("Rust".into()), status: Some(ProjectStatus::Archived), ..ProjectQuery::default() },
+            ProjectQuery { tag: Some("web".into()), language: Some("Lua".into()), status: Some(ProjectStatus::Wip), sort: ProjectSort::Alphabetical },
+        ];
+        for query in queries {
+            let string = query.to_query_string();
+            assert!(ProjectQuery::from_params(&params(&string)) == query, "`{string}` doesn't round-trip");
+        }
+
+        for sort in ProjectSort::ALL {
+            let query = ProjectQuery { sort, ..ProjectQuery::default() };
+            assert!(ProjectQuery::from_params(&params(&query.to_query_string())) == query);
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use std::{io::Cursor, sync::{Arc, OnceLock}, time::Duration};
+
+use ab_glyph::{Font, FontVec, PxScale, ScaleFont, point};
+use image::{ImageFormat, Rgb, RgbImage};
+
+use crate::{assets, util::{cache::CachePolicy, keyed_cache::KeyedCache}};
+
+const WIDTH: u32 = 1200;
+const HEIGHT: u32 = 630;
+const MARGIN: u32 = 80;
+const BACKGROUND: Rgb<u8> = Rgb([26, 26, 26]);
+const FOREGROUND: Rgb<u8> = Rgb([173, 137, 245]);
+const TITLE_SIZE: f32 = 72.0;
+const FOOTER_SIZE: f32 = 32.0;
+const MAX_LINES: usize = 4;
+/// The least recently used images are dropped past this many.
+const CACHE_LIMIT: usize = 128;
+/// A title always renders to the same image, so entries only expire to let
+/// the memory go eventually.
+const CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+fn font() -> Option<&'static FontVec> {
+    static FONT: OnceLock<Option<FontVec>> = OnceLock::new();
+    FONT.get_or_init(|| {
+        let bytes = assets::read_bytes("font/IBMPlexMono-Bold.ttf")
+            .or_else(|| { eprintln!("ERROR: Couldn't load the font for preview images"); None })?;
+        FontVec::try_from_vec(bytes)
+            .map_err(|err| eprintln!("ERROR: Couldn't parse the font for preview images: {err}"))
+            .ok()
+    }).as_ref()
+}
+
+fn text_width(font: &impl ScaleFont<&'static FontVec>, text: &str) -> f32 {
+    text.chars().map(|c| font.h_advance(font.glyph_id(c))).sum()
+}
+
+/// Greedy word wrap. Words wider than a line are left to overflow, and
+/// lines past `MAX_LINES` are dropped with an ellipsis.
+fn wrap(font: &impl ScaleFont<&'static FontVec>, text: &str, max_width: f32) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut line = String::new();
+
+    for word in text.split_whitespace() {
+        let candidate = if line.is_empty() { word.to_string() } else { format!("{line} {word}") };
+        if line.is_empty() || text_width(font, &candidate) <= max_width {
+            line = candidate;
+        } else {
+            lines.push(std::mem::replace(&mut line, word.to_string()));
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    if lines.len() > MAX_LINES {
+        lines.truncate(MAX_LINES);
+        if let Some(last) = lines.last_mut() {
+            last.push('…');
+        }
+    }
+    lines
+}
+
+fn blend(pixel: &mut Rgb<u8>, color: Rgb<u8>, coverage: f32) {
+    let coverage = coverage.clamp(0.0, 1.0);
+    for (channel, target) in pixel.0.iter_mut().zip(color.0) {
+        *channel = (*channel as f32 + (target as f32 - *channel as f32) * coverage).round() as u8;
+    }
+}
+
+fn draw_text(img: &mut RgbImage, font: &FontVec, size: f32, x: f32, baseline: f32, text: &str) {
+    let scaled = font.as_scaled(PxScale::from(size));
+    let mut caret = x;
+
+    for c in text.chars() {
+        let glyph = scaled.scaled_glyph(c);
+        let advance = scaled.h_advance(glyph.id);
+        let glyph = ab_glyph::Glyph { position: point(caret, baseline), ..glyph };
+        caret += advance;
+
+        let Some(outline) = font.outline_glyph(glyph) else { continue };
+        let bounds = outline.px_bounds();
+        outline.draw(|gx, gy, coverage| {
+            let px = bounds.min.x as i32 + gx as i32;
+            let py = bounds.min.y as i32 + gy as i32;
+            if px >= 0 && py >= 0 && (px as u32) < WIDTH && (py as u32) < HEIGHT {
+                blend(img.get_pixel_mut(px as u32, py as u32), FOREGROUND, coverage);
+            }
+        });
+    }
+}
+
+fn draw_rect(img: &mut RgbImage, inset: u32, thickness: u32) {
+    for y in inset..HEIGHT - inset {
+        for x in inset..WIDTH - inset {
+            let edge = x < inset + thickness || x >= WIDTH - inset - thickness
+                || y < inset + thickness || y >= HEIGHT - inset - thickness;
+            if edge {
+                img.put_pixel(x, y, FOREGROUND);
+            }
+        }
+    }
+}
+
+fn draw(title: &str, site_title: &str) -> Option<Vec<u8>> {
+    let font = font()?;
+    let mut img = RgbImage::from_pixel(WIDTH, HEIGHT, BACKGROUND);
+
+    // Faint scanlines, like the terminal look of the site.
+    for y in (0..HEIGHT).step_by(4) {
+        for x in 0..WIDTH {
+            blend(img.get_pixel_mut(x, y), FOREGROUND, 0.04);
+        }
+    }
+    draw_rect(&mut img, 24, 4);
+    draw_rect(&mut img, 36, 2);
+
+    let scaled = font.as_scaled(PxScale::from(TITLE_SIZE));
+    let line_height = scaled.height() + scaled.line_gap();
+    let lines = wrap(&scaled, title, (WIDTH - 2 * MARGIN) as f32);
+    let mut baseline = MARGIN as f32 + scaled.ascent();
+    for line in &lines {
+        draw_text(&mut img, font, TITLE_SIZE, MARGIN as f32, baseline, line);
+        baseline += line_height;
+    }
+
+    let footer = font.as_scaled(PxScale::from(FOOTER_SIZE));
+    let footer_baseline = (HEIGHT - MARGIN) as f32 + footer.descent();
+    draw_text(&mut img, font, FOOTER_SIZE, MARGIN as f32, footer_baseline, &format!("> {site_title}"));
+
+    let mut png = Vec::new();
+    img.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(|err| eprintln!("ERROR: Couldn't encode preview image: {err}"))
+        .ok()?;
+    Some(png)
+}
+
+fn cache() -> &'static KeyedCache<String, Vec<u8>> {
+    static CACHE: OnceLock<KeyedCache<String, Vec<u8>>> = OnceLock::new();
+    CACHE.get_or_init(|| KeyedCache::new(CachePolicy::new(CACHE_TTL), CACHE_LIMIT))
+}
+
+/// Renders a 1200x630 PNG preview card with `title` and the site name.
+/// Cached by title, the site name doesn't change while running.
+pub fn render(title: &str, site_title: &str) -> Option<Arc<Vec<u8>>> {
+    let (owned_title, site_title) = (title.to_string(), site_title.to_string());
+    cache().get_or_update(title.to_string(), move || draw(&owned_title, &site_title))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{HEIGHT, WIDTH, render};
+
+    #[test]
+    fn renders_and_caches_a_png_card() {
+        let png = render("A title long enough to wrap onto a second line of the card", "Site").unwrap();
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+
+        let image = image::load_from_memory(&png).unwrap();
+        assert_eq!((image.width(), image.height()), (WIDTH, HEIGHT));
+
+        let again = render("A title long enough to wrap onto a second line of the card", "Site").unwrap();
+        assert!(Arc::ptr_eq(&png, &again));
+    }
+}
@@ -0,0 +1,152 @@
+use std::io::Read;
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde::Deserialize;
+use tiny_http::{Header, Response};
+
+/// Headers attached to every response. The Content-Security-Policy is built
+/// per request so it can carry the nonce that `components::head` puts on its
+/// script tags.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SecurityHeaders {
+    pub script_sources: Vec<String>,
+    pub content_type_options: String,
+    pub referrer_policy: String,
+    pub permissions_policy: String,
+    pub frame_options: String,
+    pub hsts_max_age: Option<u64>,
+}
+
+impl Default for SecurityHeaders {
+    fn default() -> Self {
+        Self {
+            script_sources: Vec::new(),
+            content_type_options: "nosniff".into(),
+            referrer_policy: "strict-origin-when-cross-origin".into(),
+            permissions_policy: "camera=(), microphone=(), geolocation=()".into(),
+            frame_options: "DENY".into(),
+            hsts_max_age: None,
+        }
+    }
+}
+
+impl SecurityHeaders {
+    /// htmx evaluates `hx-on` handlers with `Function`, so `'unsafe-eval'` has
+    /// to stay in `script-src` for as long as `input_form` uses one. Inline
+    /// `style` attributes and htmx's indicator styles need `'unsafe-inline'`.
+    pub fn content_security_policy(&self, nonce: &str) -> String {
+        let mut script_src = format!("'self' 'nonce-{nonce}' 'unsafe-eval'");
+        for source in &self.script_sources {
+            script_src.push(' ');
+            script_src.push_str(source);
+        }
+
+        format!(
+            "default-src 'self'; script-src {script_src}; style-src 'self' 'unsafe-inline'; \
+             img-src 'self' data:; font-src 'self'; connect-src 'self'; object-src 'none'; \
+             base-uri 'self'; form-action 'self'; frame-ancestors 'none'"
+        )
+    }
+
+    fn headers(&self, nonce: &str) -> Vec<(&'static str, String)> {
+        let mut headers = vec![
+            ("Content-Security-Policy", self.content_security_policy(nonce)),
+            ("X-Content-Type-Options", self.content_type_options.clone()),
+            ("Referrer-Policy", self.referrer_policy.clone()),
+            ("Permissions-Policy", self.permissions_policy.clone()),
+            ("X-Frame-Options", self.frame_options.clone()),
+        ];
+
+        if let Some(max_age) = self.hsts_max_age {
+            headers.push(("Strict-Transport-Security", format!("max-age={max_age}; includeSubDomains")));
+        }
+
+        headers
+    }
+
+    /// Headers whose configured value can't be sent. Checked at startup,
+    /// since every response carries them.
+    pub fn invalid_headers(&self) -> Vec<&'static str> {
+        self.headers(&generate_nonce()).into_iter()
+            .filter(|(_, value)| !is_valid_value(value))
+            .map(|(field, _)| field)
+            .collect()
+    }
+
+    pub fn apply<R: Read>(&self, res: Response<R>, nonce: &str) -> Response<R> {
+        self.headers(nonce).into_iter()
+            .fold(res, |res, (field, value)| res.with_header(header(field, &value)))
+    }
+}
+
+/// Printable ASCII only, anything else either isn't allowed in a header or
+/// would end it early.
+fn is_valid_value(value: &str) -> bool {
+    value.bytes().all(|b| b == b'\t' || (b' '..=b'~').contains(&b))
+}
+
+fn header(field: &str, value: &str) -> Header {
+    Header::from_bytes(field, value).expect("security header values are checked by `Config::validate`")
+}
+
+pub fn generate_nonce() -> String {
+    let mut bytes = [0u8; 16];
+    getrandom::fill(&mut bytes).expect("OS random number generator unavailable");
+    STANDARD.encode(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use tiny_http::Response;
+
+    use super::{SecurityHeaders, generate_nonce};
+
+    #[test]
+    fn policy_carries_the_nonce_and_extra_sources() {
+        let security = SecurityHeaders { script_sources: vec!["https://cdn.example".into()], ..SecurityHeaders::default() };
+        let csp = security.content_security_policy("abc123==");
+
+        assert!(csp.contains("script-src 'self' 'nonce-abc123==' 'unsafe-eval' https://cdn.example;"));
+        assert!(csp.contains("frame-ancestors 'none'"));
+    }
+
+    #[test]
+    fn applies_every_header_to_the_response() {
+        let security = SecurityHeaders { hsts_max_age: Some(3600), ..SecurityHeaders::default() };
+        let nonce = generate_nonce();
+        let response = security.apply(Response::from_string("ok"), &nonce);
+
+        let value = |field: &'static str| response.headers().iter()
+            .find(|header| header.field.equiv(field))
+            .map(|header| header.value.to_string());
+
+        assert!(value("Content-Security-Policy").unwrap().contains(&format!("'nonce-{nonce}'")));
+        assert_eq!(value("X-Content-Type-Options").as_deref(), Some("nosniff"));
+        assert_eq!(value("X-Frame-Options").as_deref(), Some("DENY"));
+        assert_eq!(value("Strict-Transport-Security").as_deref(), Some("max-age=3600; includeSubDomains"));
+
+        let without_hsts = SecurityHeaders::default().apply(Response::from_string("ok"), &nonce);
+        assert!(!without_hsts.headers().iter().any(|header| header.field.equiv("Strict-Transport-Security")));
+    }
+
+    #[test]
+    fn nonces_are_fresh_base64() {
+        let nonce = generate_nonce();
+        assert_eq!(nonce.len(), 24);
+        assert!(nonce.chars().all(|c| c.is_ascii_alphanumeric() || "+/=".contains(c)));
+        assert_ne!(nonce, generate_nonce());
+    }
+
+    #[test]
+    fn reports_values_that_cant_be_sent() {
+        assert!(SecurityHeaders::default().invalid_headers().is_empty());
+
+        let security = SecurityHeaders {
+            referrer_policy: "no-referrer\r\nX-Injected: 1".into(),
+            script_sources: vec!["https://café.example".into()],
+            ..SecurityHeaders::default()
+        };
+        assert_eq!(security.invalid_headers(), ["Content-Security-Policy", "Referrer-Policy"]);
+    }
+}
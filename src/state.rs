@@ -1,24 +1,32 @@
 use std::{sync::{Arc, Mutex}, time::Duration};
 
-use crate::{api::{lastfm::{Album, Artist, LastfmApi, Track, UserStats}, wttr::WttrApi}, db::MessageDb, models::Project, util::{cache::Cache, rate_limiter::RateLimiter}};
+use crate::{access_log::AccessLog, api::{lastfm::{Album, Artist, LastfmApi, Period, Track, UserStats}, wttr::WttrApi}, blog::Blog, config::{CacheConfig, SiteConfig, UiConfig}, db::MessageDb, metrics::Metrics, models::Project, security::SecurityHeaders, util::{cache::{Cache, CachePolicy, CacheSummary}, keyed_cache::KeyedCache, rate_limiter::RateLimiter, reload::Reloadable, threadpool::PoolStats}};
+
+fn policy(config: &CacheConfig, ttl_secs: u64) -> CachePolicy {
+    CachePolicy {
+        max_stale: Duration::from_secs(config.max_stale_secs),
+        refresh_ahead: Duration::from_secs(config.refresh_ahead_secs),
+        ..CachePolicy::new(Duration::from_secs(ttl_secs))
+    }
+}
 
 #[derive(Clone)]
 pub struct LastfmCache {
     pub now_playing: Cache<Option<Track>>,
-    pub top_artists: Cache<Vec<Artist>>,
-    pub top_tracks: Cache<Vec<Track>>,
-    pub top_albums: Cache<Vec<Album>>,
+    pub top_artists: KeyedCache<Period, Vec<Artist>>,
+    pub top_tracks: KeyedCache<Period, Vec<Track>>,
+    pub top_albums: KeyedCache<Period, Vec<Album>>,
     pub user_stats: Cache<UserStats>,
 }
 
 impl LastfmCache {
-    pub fn new() -> Self {
+    pub fn new(config: &CacheConfig) -> Self {
         Self {
-            now_playing: Cache::new(Duration::from_mins(1)),
-            top_artists: Cache::new(Duration::from_hours(60)),
-            top_tracks: Cache::new(Duration::from_hours(1)),
-            top_albums: Cache::new(Duration::from_hours(1)),
-            user_stats: Cache::new(Duration::from_mins(5)),
+            now_playing: Cache::new(policy(config, config.now_playing_secs)),
+            top_artists: KeyedCache::new(policy(config, config.top_artists_secs), Period::ALL.len()),
+            top_tracks: KeyedCache::new(policy(config, config.top_tracks_secs), Period::ALL.len()),
+            top_albums: KeyedCache::new(policy(config, config.top_albums_secs), Period::ALL.len()),
+            user_stats: Cache::new(policy(config, config.user_stats_secs)),
         }
     }
 }
@@ -29,19 +37,65 @@ pub struct WttrCache {
 }
 
 impl WttrCache {
-    pub fn new() -> Self {
-        Self { weather: Cache::new(Duration::from_mins(15)) }
+    pub fn new(config: &CacheConfig) -> Self {
+        Self { weather: Cache::new(policy(config, config.weather_secs)) }
+    }
+}
+
+/// The text files shown on the home page.
+pub struct SiteText {
+    pub banner: Reloadable<String>,
+    pub welcome: Reloadable<String>,
+    pub bulletpoints: Reloadable<String>,
+}
+
+impl SiteText {
+    pub const FILES: [&'static str; 3] = ["ascii.txt", "welcome.txt", "bulletpoints.txt"];
+
+    /// Missing or unreadable files show a placeholder until they load.
+    pub fn load() -> Self {
+        let text = |content: &str| Ok(content.to_string());
+        Self {
+            banner: Reloadable::with_fallback("ascii.txt", text, "Couldn't load banner.".into()),
+            welcome: Reloadable::with_fallback("welcome.txt", text, "Couldn't load welcome message".into()),
+            bulletpoints: Reloadable::with_fallback("bulletpoints.txt", text, "☹ Couldn't load bulletpoints".into()),
+        }
     }
 }
 
 pub struct App {
-    pub wttr: WttrApi,
-    pub lastfm: LastfmApi,
+    /// Shared with background cache refreshes, which mustn't keep the whole
+    /// app alive past shutdown.
+    pub wttr: Arc<WttrApi>,
+    pub lastfm: Arc<LastfmApi>,
 
     pub wttr_cache: WttrCache,
     pub lastfm_cache: LastfmCache,
 
-    pub projects: Vec<Project>,
+    pub projects: Reloadable<Vec<Project>>,
+    pub text: SiteText,
+    pub blog: Blog,
     pub message_db: Arc<Mutex<MessageDb>>,
     pub rate_limiter: Arc<Mutex<RateLimiter>>,
+    pub security: SecurityHeaders,
+    pub htmx_integrity: String,
+    pub access_log: AccessLog,
+    pub metrics: Metrics,
+    pub pool_stats: Arc<PoolStats>,
+    pub ui: UiConfig,
+    pub site: SiteConfig,
+}
+
+impl App {
+    /// Every upstream cache by name, for the metrics and health endpoints.
+    pub fn cache_summaries(&self) -> [(&'static str, CacheSummary); 6] {
+        [
+            ("lastfm_now_playing", self.lastfm_cache.now_playing.summary()),
+            ("lastfm_top_artists", self.lastfm_cache.top_artists.summary()),
+            ("lastfm_top_tracks",  self.lastfm_cache.top_tracks.summary()),
+            ("lastfm_top_albums",  self.lastfm_cache.top_albums.summary()),
+            ("lastfm_user_stats",  self.lastfm_cache.user_stats.summary()),
+            ("wttr_weather",       self.wttr_cache.weather.summary()),
+        ]
+    }
 }
@@ -1,18 +1,70 @@
 use std::{fs, time::Duration};
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use maud::{Markup, PreEscaped, html};
+use url::form_urlencoded;
 
-use crate::{api::lastfm::{Album, Artist, Track, UserStats}, models::{Message, Project}};
+use crate::{api::lastfm::{Album, Artist, Period, Track, UserStats}, blog::Post, config::{NavItem, SiteConfig, SocialLink}, markdown::TocEntry, models::{Message, Project, ProjectFacets, ProjectQuery, ProjectSort}, ui::{Layout, OgType, PageMeta}};
+
+/// Absolute URL for a path on the site, leaving full URLs alone.
+fn absolute_url(site: &SiteConfig, url: &str) -> String {
+    if url.starts_with('/') { site.url(url) } else { url.to_string() }
+}
+
+pub fn page_meta(title: &str, meta: &PageMeta, site: &SiteConfig) -> Markup {
+    if meta.noindex {
+        return html! { meta name="robots" content="noindex"; };
+    }
+
+    let canonical = site.url(&meta.path);
+    let description = meta.description.as_deref().unwrap_or(&site.description);
+    let image = match &meta.image {
+        Some(image) => image.clone(),
+        None if site.og_images => format!("/og{}.png", meta.path),
+        None => site.image.clone(),
+    };
+    let image = absolute_url(site, &image);
+    let date = |date: NaiveDate| date.format("%Y-%m-%d").to_string();
+
+    html! {
+        meta name="description" content=(description);
+        link rel="canonical" href=(canonical);
+        meta property="og:site_name" content=(site.title);
+        meta property="og:title" content=(title);
+        meta property="og:description" content=(description);
+        meta property="og:type" content=(meta.og_type.as_str());
+        meta property="og:url" content=(canonical);
+        meta property="og:image" content=(image);
+        @if meta.og_type == OgType::Article {
+            @if let Some(published) = meta.published {
+                meta property="article:published_time" content=(date(published));
+            }
+            @if let Some(modified) = meta.modified {
+                meta property="article:modified_time" content=(date(modified));
+            }
+        }
+        meta name="twitter:card" content="summary_large_image";
+        meta name="twitter:title" content=(title);
+        meta name="twitter:description" content=(description);
+        meta name="twitter:image" content=(image);
+    }
+}
+
+pub fn head(title: &str, meta: &PageMeta, layout: &Layout) -> Markup {
+    let nonce = layout.nonce;
+    let htmx_config = format!(r#"{{"inlineScriptNonce":"{nonce}"}}"#);
 
-pub fn head(title: &str) -> Markup {
     html! {
         title { (title) }
-        script src="https://cdn.jsdelivr.net/npm/htmx.org@2.0.6/dist/htmx.min.js" {}
-        script src="static/script/message.js" {}
-        script src="static/script/server_time.js" {}
-        link rel="stylesheet" href="static/style/styles.css";
-        link rel="icon" type="image/x-icon" href="static/img/favicon.ico";
+        (page_meta(title, meta, layout.site))
+        link rel="alternate" type="application/atom+xml" title=(layout.site.title) href="/feed.atom";
+        link rel="sitemap" type="application/xml" href="/sitemap.xml";
+        meta name="htmx-config" content=(htmx_config);
+        script nonce=(nonce) src="/static/script/htmx.min.js" integrity=(layout.htmx_integrity) {}
+        script nonce=(nonce) src="/static/script/message.js" {}
+        script nonce=(nonce) src="/static/script/server_time.js" {}
+        link rel="stylesheet" href="/static/style/styles.css";
+        link rel="icon" type="image/x-icon" href="/static/img/favicon.ico";
     }
 }
 
@@ -21,11 +73,11 @@ pub fn footer() -> Markup {
         footer.double-border.font-small.flex-row  {
             p { "Made with " }
             a href="https://www.htmx.org" target="_blank" rel="noopener noreferrer" {
-                img src="static/img/htmx.svg" alt="HTMX" height="16";
+                img src="/static/img/htmx.svg" alt="HTMX" height="16";
             }
             p { " and "}
             a href="https://rust-lang.org/" target="_blank" rel="noopener noreferrer" {
-                img src="static/img/rust.svg" alt="Rust" height="16";
+                img src="/static/img/rust.svg" alt="Rust" height="16";
             }
         }
     }
@@ -37,36 +89,25 @@ fn nav_item(endpoint: &str, name: &str) -> Markup {
     }
 }
 
-pub fn navbar(items: &[(&str, &str)]) -> Markup {
+pub fn navbar(items: &[NavItem]) -> Markup {
     html! {
         section .double-border.flex-row.gap8
             hx-boost="true"
             hx-target="#content"
             hx-push-url="true"
-        { @for item in items { (nav_item(item.0, item.1)) } }
+        { @for item in items { (nav_item(&item.href, &item.label)) } }
     }
 }
 
-fn load_text_from_file(path: &str) -> Option<String> {
-    fs::read_to_string(path)
-        .map_err(|e| eprintln!("ERROR: couldn't open file `{path}`: {e}"))
-        .ok()
-}
-
-pub fn ascii_banner() -> Markup {
-    let banner = load_text_from_file("./static/ascii.txt").unwrap_or("Couldn't load banner.".into());
+pub fn ascii_banner(banner: &str) -> Markup {
     html! { pre.ascii-banner { (banner) } }
 }
 
-pub fn welcome_message() -> Markup {
-    let message = load_text_from_file("./static/welcome.txt").unwrap_or("Couldn't load welcome message".into());
+pub fn welcome_message(message: &str) -> Markup {
     html! { marquee.welcome-message scrollamount="5" { (message) } }
 }
 
-pub fn bulletpoints() -> Markup {
-    let bulletpoints = load_text_from_file("./static/bulletpoints.txt")
-        .unwrap_or("☹ Couldn't load bulletpoints".into());
-
+pub fn bulletpoints(bulletpoints: &str) -> Markup {
     html! {
         div.flex-column {
             @for point in bulletpoints.lines() {
@@ -76,38 +117,62 @@ pub fn bulletpoints() -> Markup {
     }
 }
 
-pub fn socials() -> Markup {
+pub fn socials(links: &[SocialLink]) -> Markup {
     html! {
         div.flex-row.gap4 {
-            a.center.border.flex-grow href="https://tidal.com/artist/64262665" target="_blank" rel="noopener noreferrer" {
-                img src="static/img/tidal.svg" alt="HTMX" height="24";
+            @for link in links {
+                a.center.border.flex-grow href=(link.url) target="_blank" rel="noopener noreferrer" {
+                    img src=(link.icon) alt=(link.alt) height="24";
+                }
             }
+        }
+    }
+}
 
-            a.center.border.flex-grow href="https://x.com/achtergesteld" target="_blank" rel="noopener noreferrer" {
-                img src="static/img/x.svg" alt="HTMX" height="24";
-            }
+/// How often a component that couldn't get its data tries again.
+const UNAVAILABLE_RETRY: &str = "30s";
 
-            a.center.border.flex-grow href="https://twitch.tv/mentaalachtergesteld" target="_blank" rel="noopener noreferrer" {
-                img src="static/img/twitch.svg" alt="HTMX" height="24";
-            }
+/// Data of a component that loads itself after the page.
+pub enum Lazy<T> {
+    /// Rendered into the page, fetches its data once loaded.
+    Loading,
+    Ready(T),
+    /// Upstream failed and nothing is cached.
+    Unavailable,
+}
 
-            a.center.border.flex-grow href="https://github.com/mentaalachtergesteld" target="_blank" rel="noopener noreferrer" {
-                img src="static/img/github.svg" alt="HTMX" height="24";
-            }
+impl<T> Lazy<T> {
+    fn map<U>(self, f: impl FnOnce(T) -> U) -> Lazy<U> {
+        match self {
+            Self::Loading => Lazy::Loading,
+            Self::Ready(value) => Lazy::Ready(f(value)),
+            Self::Unavailable => Lazy::Unavailable,
         }
     }
 }
 
+/// What a cache lookup returned, `None` when the fetch failed.
+impl<T> From<Option<T>> for Lazy<T> {
+    fn from(data: Option<T>) -> Self {
+        data.map_or(Self::Unavailable, Self::Ready)
+    }
+}
+
 pub fn lazy_component(
-    content: Option<Markup>,
+    content: Lazy<Markup>,
     placeholder: Markup,
+    source: &str,
     endpoint: &str,
     interval: &str,
     is_inline: bool
 ) -> Markup {
     let (trigger, inner_html) = match content {
-        Some(html) => (format!("every {interval}"), html),
-        None => ("load".to_string(), placeholder)
+        Lazy::Ready(html) => (format!("every {interval}"), html),
+        Lazy::Loading => ("load".to_string(), placeholder),
+        Lazy::Unavailable => (
+            format!("every {UNAVAILABLE_RETRY}"),
+            html! { span.unavailable { "☹ " (source) " unavailable ☹" } },
+        ),
     };
 
     let tag = if is_inline { "span" } else { "div" };
@@ -136,16 +201,16 @@ fn skeleton_span(text: &str) -> Markup {
     }
 }
 
-pub fn now_playing(data: Option<&Option<Track>>) -> Markup {
+pub fn now_playing(data: Lazy<&Option<Track>>) -> Markup {
     let content = data.map(|track_opt| match track_opt {
         Some(track) => html! { "♫ Now Playing: " (track.name) " by " (track.artist) " ♫" },
         None => html! { "☹ Nothing playing right now ☹" }
     });
 
-    lazy_component(content, skeleton_span("loading current track..."), "/comp/now-playing", "1m", true)
+    lazy_component(content, skeleton_span("loading current track..."), "Last.fm", "/comp/now-playing", "1m", true)
 }
 
-pub fn top_artists(data: Option<&Vec<Artist>>) -> Markup {
+pub fn top_artists(data: Lazy<&Vec<Artist>>, period: Period) -> Markup {
     let content = data.map(|ta| html! {
         div.flex-column.gap4 { @for (i, artist) in ta.iter().enumerate() {
             div.list-row {
@@ -155,10 +220,10 @@ pub fn top_artists(data: Option<&Vec<Artist>>) -> Markup {
         }} 
     });
 
-    lazy_component(content, skeleton_list("loading artist...", 10), "/comp/top-artists", "", false)
+    lazy_component(content, skeleton_list("loading artist...", 10), "Last.fm", &format!("/comp/top-artists?period={}", period.as_str()), "", false)
 }
 
-pub fn top_tracks(data: Option<&Vec<Track>>) -> Markup {
+pub fn top_tracks(data: Lazy<&Vec<Track>>, period: Period) -> Markup {
     let content = data.map(|tt| html! {
         div.flex-column.gap4 { @for (i, track) in tt.iter().enumerate() {
             div.list-row {
@@ -170,10 +235,10 @@ pub fn top_tracks(data: Option<&Vec<Track>>) -> Markup {
         }}
     });
 
-    lazy_component(content, skeleton_list("loading track...", 10), "/comp/top-tracks", "", false)
+    lazy_component(content, skeleton_list("loading track...", 10), "Last.fm", &format!("/comp/top-tracks?period={}", period.as_str()), "", false)
 }
 
-pub fn top_albums(data: Option<&Vec<Album>>) -> Markup {
+pub fn top_albums(data: Lazy<&Vec<Album>>, period: Period) -> Markup {
     let content = data.map(|ta| html! {
         div.flex-column.gap4 { @for (i, album) in ta.iter().enumerate() {
             div.list-row {
@@ -185,10 +250,10 @@ pub fn top_albums(data: Option<&Vec<Album>>) -> Markup {
         }}
     });
 
-    lazy_component(content, skeleton_list("loading album...", 10), "/comp/top-albums", "", false)
+    lazy_component(content, skeleton_list("loading album...", 10), "Last.fm", &format!("/comp/top-albums?period={}", period.as_str()), "", false)
 }
 
-pub fn lastfm_user_stats(data: Option<&UserStats>) -> Markup {
+pub fn lastfm_user_stats(data: Lazy<&UserStats>) -> Markup {
     let content = data.map(|us| html! {
         div.flex-column.gap4 {
             span { "Total scrobbles: " (us.total_scrobbles) }
@@ -207,26 +272,41 @@ pub fn lastfm_user_stats(data: Option<&UserStats>) -> Markup {
         }
     };
 
-    lazy_component(content, placeholder, "/comp/user-stats", "", false)
+    lazy_component(content, placeholder, "Last.fm", "/comp/user-stats", "", false)
 }
 
-pub fn lastfm_stats() -> Markup {
+fn period_tab(period: Period, active: bool) -> Markup {
+    let query = format!("?period={}", period.as_str());
     html! {
+        a.chip.active[active]
+            href=(format!("/interests{query}"))
+            hx-get=(format!("/comp/lastfm-stats{query}"))
+            hx-target="#lastfm-stats"
+            hx-push-url=(format!("/interests{query}"))
+            { (period.label()) }
+    }
+}
+
+pub fn lastfm_stats(period: Period) -> Markup {
+    html! {
+        div.flex-row.flex-wrap.gap4.justify-center.font-small {
+            @for p in Period::ALL { (period_tab(p, p == period)) }
+        }
         div.flex-row.gap4.justify-center {
             div.flex-column.gap4.w50 {
                 div.align-center.border {
                     div.flex-row.align-center.justify-center.gap4 {
                         h1 { "Top Artists " }
-                        span.font-tiny { "(1 month)" }
+                        span.font-tiny { "(" (period.label()) ")" }
                     }
-                    (top_artists(None))
+                    (top_artists(Lazy::Loading, period))
                 }
                 div.align-center.border {
                     div.flex-row.align-center.justify-center.gap4 {
                         h1 { "Top Tracks" }
-                        span.font-tiny { "(1 month)" }
+                        span.font-tiny { "(" (period.label()) ")" }
                     }
-                    (top_tracks(None))
+                    (top_tracks(Lazy::Loading, period))
                 }
             }
             div.flex-column.gap4.w50 {
@@ -234,24 +314,23 @@ pub fn lastfm_stats() -> Markup {
                     div.flex-row.align-center.justify-center.gap4 {
                         h1 { "User Stats" }
                     }
-                    (lastfm_user_stats(None))
+                    (lastfm_user_stats(Lazy::Loading))
                 }
                 div.align-center.border {
                     div.flex-row.align-center.justify-center.gap4 {
                         h1 { "Top Albums" }
-                        span.font-tiny { "(1 month)" }
+                        span.font-tiny { "(" (period.label()) ")" }
                     }
-                    (top_albums(None))
+                    (top_albums(Lazy::Loading, period))
                 }
             }
         }
     }
-
 }
 
-pub fn server_weather(data: Option<&String>) -> Markup {
+pub fn server_weather(data: Lazy<&String>) -> Markup {
     let content = data.map(|t| html! { (t) });
-    lazy_component(content, skeleton_span("loading weather..."), "/comp/server-weather", "5m", true)
+    lazy_component(content, skeleton_span("loading weather..."), "wttr.in", "/comp/server-weather", "5m", true)
 }
 
 pub fn server_clock() -> Markup {
@@ -279,19 +358,51 @@ pub fn smart_time(timestamp: DateTime<Utc>) -> Markup {
     html! { span.live-time data-ts=(ts_millis) data-type="smart" {} }
 }
 
+/// Link that swaps `#content` when htmx is around and is a normal link
+/// otherwise.
+pub fn page_link(href: &str, label: &str) -> Markup {
+    html! {
+        a.nav-link href=(href) hx-get=(href) hx-target="#content" hx-push-url="true" hx-swap="innerHTML show:window:top" { (label) }
+    }
+}
+
+pub fn project_meta(project: &Project) -> Markup {
+    html! {
+        @if project.status.is_some() || project.started.is_some() || !project.languages.is_empty() || !project.tags.is_empty() {
+            div.flex-row.flex-wrap.gap8.font-tiny {
+                @if let Some(status) = project.status { span { "[" (status.label()) "]" } }
+                @if let Some((year, month, _)) = project.started_on() { span { "since " (format!("{year}-{month:02}")) } }
+                @if !project.languages.is_empty() { span { (project.languages.join(", ")) } }
+                @for tag in &project.tags { span { "#" (tag) } }
+            }
+        }
+    }
+}
+
+pub fn project_links(project: &Project) -> Markup {
+    html! {
+        a.nav-link href=(project.source_url) target="_blank" rel="noopener noreferrer" { "Source" }
+        @if let Some(deploy_url) = &project.deploy_url {
+            a.nav-link href=(deploy_url) target="_blank" rel="noopener noreferrer" { "Deployed" }
+        }
+    }
+}
+
 pub fn project_item(project: &Project) -> Markup {
     html! {
         div.border.project.font-small.flex-row {
             div.flex-column.flex-grow.space-between {
                 div {
-                    h3 { (project.title) }
+                    h3 {
+                        @if project.featured { "★ " }
+                        (project.title)
+                    }
                     p { (project.description) }
+                    (project_meta(project))
                 } 
                 div {
-                    a.nav-link href=(project.source_url) target="_blank" rel="noopener noreferrer" { "Source" }
-                    @if let Some(deploy_url) = &project.deploy_url {
-                        a.nav-link href=(deploy_url) target="_blank" rel="noopener noreferrer" { "Deployed" }
-                    }
+                    (page_link(&format!("/projects/{}", project.slug), "Details"))
+                    (project_links(project))
                 }
             }
             @if let Some(image_url) = &project.image_url {
@@ -301,14 +412,136 @@ pub fn project_item(project: &Project) -> Markup {
     }
 }
 
-pub fn projects_list(projects: &[Project], last_id: Option<usize>) -> Markup {
+/// Filter chip that navigates the whole page, unlike the project filters
+/// which only swap the list.
+pub fn tag_chip(label: &str, href: &str, active: bool) -> Markup {
+    html! {
+        a.chip.active[active] href=(href) hx-get=(href) hx-target="#content" hx-push-url="true" { (label) }
+    }
+}
+
+pub fn blog_tag_chip(tag: &str, active: bool) -> Markup {
+    // Clicking the active tag goes back to all posts.
+    let href = if active {
+        "/blog".to_string()
+    } else {
+        format!("/blog?{}", form_urlencoded::Serializer::new(String::new()).append_pair("tag", tag).finish())
+    };
+    tag_chip(tag, &href, active)
+}
+
+pub fn post_item(post: &Post) -> Markup {
+    html! {
+        div.border.flex-column.gap4.font-small {
+            div.flex-row.space-between.align-center {
+                h3 { (post.title) }
+                span.font-tiny { (post.date.format("%Y-%m-%d")) }
+            }
+            @if let Some(summary) = &post.summary { p { (summary) } }
+            div.flex-row.flex-wrap.gap4.align-center {
+                (page_link(&format!("/blog/{}", post.slug), "Read"))
+                @if post.draft { span.font-tiny { "[Draft]" } }
+                @for tag in &post.tags { (blog_tag_chip(tag, false)) }
+            }
+        }
+    }
+}
+
+pub fn table_of_contents(toc: &[TocEntry]) -> Markup {
+    html! {
+        nav.border.toc.font-small {
+            h4 { "Contents" }
+            ul {
+                @for entry in toc {
+                    li class=(format!("toc-level{}", entry.level)) {
+                        a.nav-link href=(format!("#{}", entry.id)) { (entry.text) }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn query_suffix(query: &ProjectQuery) -> String {
+    let query = query.to_query_string();
+    if query.is_empty() { query } else { format!("?{query}") }
+}
+
+/// Clicking an active chip clears that filter again.
+fn toggled<T: PartialEq>(current: &Option<T>, value: T) -> Option<T> {
+    if current.as_ref() == Some(&value) { None } else { Some(value) }
+}
+
+fn filter_chip(label: &str, query: &ProjectQuery, active: bool) -> Markup {
+    let suffix = query_suffix(query);
+    html! {
+        a.chip.active[active]
+            href=(format!("/projects{suffix}"))
+            hx-get=(format!("/comp/projects{suffix}"))
+            hx-target="#projects-container"
+            hx-push-url=(format!("/projects{suffix}"))
+            { (label) }
+    }
+}
+
+pub fn project_filters(facets: &ProjectFacets, query: &ProjectQuery) -> Markup {
+    html! {
+        div.flex-column.gap4.font-small {
+            @if !facets.tags.is_empty() {
+                div.flex-row.flex-wrap.gap4.align-center {
+                    span { "Tags:" }
+                    @for tag in &facets.tags {
+                        (filter_chip(tag, &ProjectQuery { tag: toggled(&query.tag, tag.clone()), ..query.clone() }, query.tag.as_ref() == Some(tag)))
+                    }
+                }
+            }
+            @if !facets.languages.is_empty() {
+                div.flex-row.flex-wrap.gap4.align-center {
+                    span { "Languages:" }
+                    @for language in &facets.languages {
+                        (filter_chip(language, &ProjectQuery { language: toggled(&query.language, language.clone()), ..query.clone() }, query.language.as_ref() == Some(language)))
+                    }
+                }
+            }
+            @if !facets.statuses.is_empty() {
+                div.flex-row.flex-wrap.gap4.align-center {
+                    span { "Status:" }
+                    @for status in &facets.statuses {
+                        (filter_chip(status.label(), &ProjectQuery { status: toggled(&query.status, *status), ..query.clone() }, query.status == Some(*status)))
+                    }
+                }
+            }
+            div.flex-row.flex-wrap.gap4.align-center {
+                span { "Sort:" }
+                @for sort in ProjectSort::ALL {
+                    (filter_chip(sort.label(), &ProjectQuery { sort, ..query.clone() }, query.sort == sort))
+                }
+            }
+        }
+    }
+}
+
+pub fn project_browser(facets: &ProjectFacets, query: &ProjectQuery, projects: &[&Project], last_id: Option<usize>) -> Markup {
+    html! {
+        (project_filters(facets, query))
+        @if projects.is_empty() {
+            p.center { "No projects match these filters." }
+        }
+        (projects_list(projects, last_id, query))
+    }
+}
+
+pub fn projects_list(projects: &[&Project], last_id: Option<usize>, query: &ProjectQuery) -> Markup {
+    let mut params = query.to_query_string();
+    if !params.is_empty() { params.push('&'); }
+
     html! {
         @for p in projects {
             (project_item(p))
         }
         @if let Some(last_id) = last_id {
             span #load-more-trigger
-                hx-get=(format!("/comp/projects?last_id={}", last_id)) 
+                hx-get=(format!("/comp/projects?{params}last_id={last_id}")) 
                 hx-trigger="revealed"
                 hx-target="#load-more-trigger"
                 hx-swap="outerHTML"
@@ -401,3 +634,45 @@ pub fn form_feedback(title: &str, desc: &str, is_error: bool) -> Markup {
 pub fn empty_form_feedback() -> Markup {
     html! { div #form-feedback hx-swap-oob="true" {} }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{config::SiteConfig, ui::PageMeta};
+
+    use super::page_meta;
+
+    fn site() -> SiteConfig {
+        SiteConfig { base_url: "https://example.com/".into(), ..SiteConfig::default() }
+    }
+
+    #[test]
+    fn makes_links_absolute() {
+        let meta = PageMeta { image: Some("/static/img/card.png".into()), ..PageMeta::new("/projects") };
+        let html = page_meta("Projects", &meta, &site()).into_string();
+
+        assert!(html.contains(r#"<link rel="canonical" href="https://example.com/projects">"#));
+        assert!(html.contains(r#"<meta property="og:url" content="https://example.com/projects">"#));
+        assert!(html.contains(r#"<meta property="og:image" content="https://example.com/static/img/card.png">"#));
+
+        let meta = PageMeta { image: Some("https://cdn.example.net/card.png".into()), ..PageMeta::new("/") };
+        assert!(page_meta("Home", &meta, &site()).into_string()
+            .contains(r#"<meta name="twitter:image" content="https://cdn.example.net/card.png">"#));
+    }
+
+    #[test]
+    fn points_at_the_generated_image_when_enabled() {
+        let site = SiteConfig { og_images: true, ..site() };
+        let html = page_meta("Blog", &PageMeta::new("/blog"), &site).into_string();
+        assert!(html.contains(r#"<meta property="og:image" content="https://example.com/og/blog.png">"#));
+    }
+
+    #[test]
+    fn escapes_titles_and_descriptions() {
+        let meta = PageMeta { description: Some(r#"Say "hi" & <wave>"#.into()), ..PageMeta::new("/") };
+        let html = page_meta("Fish & <Chips>", &meta, &site()).into_string();
+
+        assert!(html.contains(r#"<meta property="og:title" content="Fish &amp; &lt;Chips&gt;">"#));
+        assert!(html.contains(r#"<meta name="description" content="Say &quot;hi&quot; &amp; &lt;wave&gt;">"#));
+        assert!(!html.contains("<Chips>") && !html.contains("<wave>"));
+    }
+}
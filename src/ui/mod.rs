@@ -1,24 +1,67 @@
+use chrono::NaiveDate;
 use maud::{DOCTYPE, Markup, html};
 
+use crate::config::{NavItem, SiteConfig};
+
 pub mod components;
 pub mod pages;
 
-const NAVBAR_ITEMS: [(&str, &str); 4] = [
-    ("/home",      "Home"),
-    ("/guestbook", "Guestbook"),
-    ("/projects",  "Projects"),
-    ("/interests", "Interests"),
-];
+/// Everything around the page content that `render_full` needs.
+pub struct Layout<'a> {
+    pub nonce: &'a str,
+    pub htmx_integrity: &'a str,
+    pub navbar: &'a [NavItem],
+    pub site: &'a SiteConfig,
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Eq)]
+pub enum OgType {
+    #[default]
+    Website,
+    Article,
+}
+
+impl OgType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Website => "website",
+            Self::Article => "article",
+        }
+    }
+}
+
+/// Per-page metadata for search engines and link previews. Anything left
+/// empty falls back to the site-wide values in `SiteConfig`.
+#[derive(Default)]
+pub struct PageMeta {
+    /// Canonical path of the page, without the query string.
+    pub path: String,
+    pub description: Option<String>,
+    /// Path under the site or an absolute URL.
+    pub image: Option<String>,
+    pub og_type: OgType,
+    pub published: Option<NaiveDate>,
+    pub modified: Option<NaiveDate>,
+    /// Keeps the page out of search results and link previews, for error
+    /// pages that shouldn't have a canonical URL.
+    pub noindex: bool,
+}
+
+impl PageMeta {
+    pub fn new(path: &str) -> Self {
+        Self { path: path.to_string(), ..Self::default() }
+    }
+}
 
-pub fn render_full(title: &str, content: Markup) -> Markup {
+pub fn render_full(title: &str, content: Markup, meta: &PageMeta, layout: &Layout) -> Markup {
     html! {
         (DOCTYPE)
         html lang="en" {
-            (components::head(title))
+            (components::head(title, meta, layout))
         }
         body {
             section.flex-column #main {
-                (components::navbar(&NAVBAR_ITEMS))
+                (components::navbar(layout.navbar))
                 section.flex-column #content { (content) }
                 (components::footer())
             }
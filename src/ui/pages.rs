@@ -1,43 +1,45 @@
-use maud::{html, Markup};
+use std::collections::BTreeSet;
 
-use crate::ui::components;
+use maud::{html, Markup, PreEscaped};
 
-pub fn home() -> Markup {
+use crate::{api::lastfm::Period, blog::Post, config::SocialLink, markdown::Document, models::{Project, ProjectQuery}, state::SiteText, ui::components::{self, Lazy}};
+
+pub fn home(text: &SiteText, socials: &[SocialLink]) -> Markup {
     html! {
         section.double-border.flex-column.gap8 {
-            (components::ascii_banner())
-            (components::welcome_message())
+            (components::ascii_banner(&text.banner.get()))
+            (components::welcome_message(&text.welcome.get()))
         }
-        img.border.flex-grow src="static/img/underconstruction.gif";
+        img.border.flex-grow src="/static/img/underconstruction.gif";
         section.double-border.flex-column.gap8 {
             div.flex-row.gap8.align-center.font-small {
-                img src="static/img/rattlesnake.gif";
-                (components::bulletpoints())
+                img src="/static/img/rattlesnake.gif";
+                (components::bulletpoints(&text.bulletpoints.get()))
             }
             div.flex-row.gap4 {
                 marquee.flex-row.center.border.flex-grow
                     scrollamount="2" behavior="alternate"
-                    { (components::now_playing(None)) }
+                    { (components::now_playing(Lazy::Loading)) }
                 span.center.border { (components::server_clock()) }
             }
             div.flex-row.gap4 {
-                span.center.border { (components::server_weather(None)) } 
+                span.center.border { (components::server_weather(Lazy::Loading)) } 
                 span.center.border.flex-grow { (components::server_uptime()) }
             }
 
-            (components::socials())
+            (components::socials(socials))
         }
         section.border.flex-row.justify-center.gap8 {
-            img src="static/img/linuxflipping.gif";
-            img src="static/img/gator.gif";
-            img src="static/img/eu.gif";
+            img src="/static/img/linuxflipping.gif";
+            img src="/static/img/gator.gif";
+            img src="/static/img/eu.gif";
         }
     } 
 }
 
 pub fn guestbook() -> Markup {
     html! {
-        img.border.flex-grow src="static/img/underconstruction.gif";
+        img.border.flex-grow src="/static/img/underconstruction.gif";
         section.double-border.flex-column.gap8.justify-center {
             (components::input_form())
             div #message-container
@@ -55,12 +57,15 @@ pub fn guestbook() -> Markup {
     }
 }
 
-pub fn projects() -> Markup {
+pub fn projects(query: &ProjectQuery) -> Markup {
+    let query = query.to_query_string();
+    let endpoint = if query.is_empty() { "/comp/projects".to_string() } else { format!("/comp/projects?{query}") };
+
     html! {
-        img.border.flex-grow src="static/img/underconstruction.gif";
+        img.border.flex-grow src="/static/img/underconstruction.gif";
         section.double-border.flex-column.gap8.justify-center {
-            div #projects-container
-                hx-get="/comp/projects"
+            div.flex-column.gap8 #projects-container
+                hx-get=(endpoint)
                 hx-trigger="load"
                 hx-swap="innerHTML"
             { "Loading projects..." }
@@ -68,12 +73,80 @@ pub fn projects() -> Markup {
     }
 }
 
-pub fn interests() -> Markup {
+pub fn project(project: &Project, document: Option<&Document>) -> Markup {
+    html! {
+        section.double-border.flex-column.gap8 {
+            div { (components::page_link("/projects", "Back to projects")) }
+            div.flex-row.gap8.space-between {
+                div.flex-column.gap4 {
+                    h1 { (project.title) }
+                    p { (project.description) }
+                    (components::project_meta(project))
+                    div { (components::project_links(project)) }
+                }
+                @if let Some(image_url) = &project.image_url {
+                    img.border style="max-height: 128px;" src=(image_url);
+                }
+            }
+        }
+        @if let Some(document) = document {
+            section.double-border.flex-column.gap8 {
+                @if document.toc.len() > 1 {
+                    (components::table_of_contents(&document.toc))
+                }
+                article.markdown { (PreEscaped(&document.html)) }
+            }
+        }
+    }
+}
+
+pub fn blog(posts: &[&Post], tags: &BTreeSet<&str>, active_tag: Option<&str>) -> Markup {
+    html! {
+        img.border.flex-grow src="/static/img/underconstruction.gif";
+        section.double-border.flex-column.gap8 {
+            h1.center { "Blog" }
+            @if !tags.is_empty() {
+                div.flex-row.flex-wrap.gap4.align-center.font-small {
+                    span { "Tags:" }
+                    @for tag in tags { (components::blog_tag_chip(tag, active_tag == Some(*tag))) }
+                }
+            }
+            @if posts.is_empty() {
+                p.center {
+                    @if let Some(tag) = active_tag { "No posts tagged " (tag) " yet." } @else { "No posts yet." }
+                }
+            }
+            @for post in posts { (components::post_item(post)) }
+        }
+    }
+}
+
+pub fn post(post: &Post) -> Markup {
+    html! {
+        section.double-border.flex-column.gap8 {
+            div { (components::page_link("/blog", "Back to blog")) }
+            h1 { (post.title) }
+            div.flex-row.flex-wrap.gap4.align-center.font-small {
+                span { (post.date.format("%Y-%m-%d")) }
+                @if post.draft { span { "[Draft]" } }
+                @for tag in &post.tags { (components::blog_tag_chip(tag, false)) }
+            }
+        }
+        section.double-border.flex-column.gap8 {
+            @if post.document.toc.len() > 1 {
+                (components::table_of_contents(&post.document.toc))
+            }
+            article.markdown { (PreEscaped(&post.document.html)) }
+        }
+    }
+}
+
+pub fn interests(period: Period) -> Markup {
     html! {
-        img.border.flex-grow src="static/img/underconstruction.gif";
+        img.border.flex-grow src="/static/img/underconstruction.gif";
         section.double-border.flex-column.gap8.justify-center {
             h1.center { "Last.fm stats" }
-            (components::lastfm_stats())
+            div #lastfm-stats.flex-column.gap4 { (components::lastfm_stats(period)) }
         }
     }
 }
@@ -82,7 +155,7 @@ pub fn not_found() -> Markup {
     html! {
         section.double-border.flex-column.align-center.gap4 {
             h1.center { "Not Found" }
-            img src="static/img/dassen.png";
+            img src="/static/img/dassen.png";
         }
     }
 }
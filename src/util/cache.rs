@@ -1,51 +1,65 @@
-use std::{sync::{Arc, RwLock}, time::{Duration, Instant}};
+use std::{sync::Arc, time::Duration};
 
-struct CacheState<T> {
-    data: Option<Arc<T>>,
-    last_updated: Instant,
+use chrono::{DateTime, Utc};
+
+use crate::util::keyed_cache::{KeyedCache, SavedEntry};
+
+/// How long cached values are fresh, and what happens around expiry.
+#[derive(Clone, Copy)]
+pub struct CachePolicy {
+    pub ttl: Duration,
+    /// How long past the TTL a value is still served while it refreshes in
+    /// the background. After that, callers wait for the fetch.
+    pub max_stale: Duration,
+    /// Starts a background refresh this long before the TTL runs out, so
+    /// busy entries never go stale.
+    pub refresh_ahead: Duration,
 }
 
-impl<T> CacheState<T> {
-    pub fn is_valid(&self, ttl: Duration) -> bool {
-        self.data.is_some() && self.last_updated.elapsed() < ttl
+impl CachePolicy {
+    /// Values expire after `ttl` and are never served stale.
+    pub fn new(ttl: Duration) -> Self {
+        Self { ttl, max_stale: Duration::ZERO, refresh_ahead: Duration::ZERO }
     }
 }
 
+pub struct CacheSummary {
+    pub hits: u64,
+    pub misses: u64,
+    /// Lookups answered with an expired value while it was refreshing.
+    pub stale: u64,
+    /// Entries dropped to make room for new ones.
+    pub evictions: u64,
+    /// Wall-clock time of the last successful fetch, if there was one.
+    pub refreshed_at: Option<DateTime<Utc>>,
+}
+
+/// Cache for a single value.
 #[derive(Clone)]
 pub struct Cache<T> {
-    state: Arc<RwLock<CacheState<T>>>,
-    ttl: Duration,
+    inner: KeyedCache<(), T>,
 }
 
 impl<T: Send + Sync + 'static> Cache<T> {
-    pub fn new(ttl: Duration) -> Self {
-        Self {
-            state: Arc::new(RwLock::new(CacheState {
-                data: None,
-                last_updated: Instant::now() - ttl - ttl,
-            })),
-            ttl,
-        }
+    pub fn new(policy: CachePolicy) -> Self {
+        Self { inner: KeyedCache::new(policy, 1) }
     }
 
-    pub fn get_or_update<F>(&self, fetcher: F) -> Option<Arc<T>>
-    where F: FnOnce() -> Option<T>,
-    {
-        if let Ok(guard) = self.state.read() {
-            if guard.is_valid(self.ttl) { return guard.data.clone() }
-        }
-
-        let mut guard = self.state.write().unwrap();
+    pub fn summary(&self) -> CacheSummary {
+        self.inner.summary()
+    }
 
-        if guard.is_valid(self.ttl) { return guard.data.clone() }
+    pub fn snapshot(&self) -> Vec<SavedEntry<(), T>> {
+        self.inner.snapshot()
+    }
 
-        if let Some(fresh_data) = fetcher() {
-            let arc_data = Arc::new(fresh_data);
-            guard.data = Some(arc_data.clone());
-            guard.last_updated = Instant::now();
-            return Some(arc_data);
-        }
+    pub fn restore(&self, saved: Vec<SavedEntry<(), T>>) {
+        self.inner.restore(saved)
+    }
 
-        guard.data.clone()
+    pub fn get_or_update<F>(&self, fetcher: F) -> Option<Arc<T>>
+    where F: FnOnce() -> Option<T> + Send + 'static,
+    {
+        self.inner.get_or_update((), fetcher)
     }
 }
@@ -0,0 +1,545 @@
+use std::{collections::HashMap, hash::Hash, sync::{Arc, Condvar, Mutex, atomic::{AtomicU64, Ordering}}, thread, time::{Duration, Instant}};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::util::{LockExt, cache::{CachePolicy, CacheSummary}};
+
+struct Entry<V> {
+    value: Arc<V>,
+    expires_at: Instant,
+    refreshed_at: DateTime<Utc>,
+    /// Value of `Inner::clock` when the entry was last read or written.
+    last_used: u64,
+}
+
+/// An entry as it's written to disk. Times are wall-clock so they survive a
+/// restart.
+#[derive(Serialize, Deserialize)]
+pub struct SavedEntry<K, V> {
+    pub key: K,
+    pub value: Arc<V>,
+    pub refreshed_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// A fetch in progress. Callers that miss on the same key wait for its
+/// result instead of fetching again.
+struct Flight<V> {
+    result: Mutex<Option<Option<Arc<V>>>>,
+    done: Condvar,
+}
+
+impl<V> Flight<V> {
+    fn wait(&self) -> Option<Arc<V>> {
+        let mut result = self.result.lock_recover();
+        loop {
+            if let Some(value) = result.as_ref() {
+                return value.clone();
+            }
+            result = self.done.wait(result).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    fn finish(&self, value: Option<Arc<V>>) {
+        *self.result.lock_recover() = Some(value);
+        self.done.notify_all();
+    }
+}
+
+struct Inner<K, V> {
+    entries: HashMap<K, Entry<V>>,
+    in_flight: HashMap<K, Arc<Flight<V>>>,
+    clock: u64,
+}
+
+#[derive(Default)]
+struct KeyedCacheStats {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    stale: AtomicU64,
+    evictions: AtomicU64,
+}
+
+/// Cache with an entry per key. Entries expire after their TTL, and once
+/// there are `max_entries` the least recently used one makes room for a
+/// new one. Meant for a handful of keys, eviction scans every entry.
+///
+/// Expired entries are served for up to `max_stale` while a single
+/// background thread fetches the new value.
+pub struct KeyedCache<K, V> {
+    inner: Arc<Mutex<Inner<K, V>>>,
+    policy: CachePolicy,
+    max_entries: usize,
+    stats: Arc<KeyedCacheStats>,
+}
+
+// Derived `Clone` would want `K: Clone` and `V: Clone`, only the handles
+// are cloned here.
+impl<K, V> Clone for KeyedCache<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+            policy: self.policy,
+            max_entries: self.max_entries,
+            stats: Arc::clone(&self.stats),
+        }
+    }
+}
+
+/// Leader of a fetch. Dropping it without `finish`, when the fetcher
+/// panicked or its thread couldn't start, still wakes up the waiters so
+/// they don't hang forever.
+struct FlightGuard<K: Clone + Eq + Hash, V> {
+    cache: KeyedCache<K, V>,
+    key: Option<K>,
+    flight: Arc<Flight<V>>,
+}
+
+impl<K: Clone + Eq + Hash, V> FlightGuard<K, V> {
+    fn finish(mut self, ttl: Duration, fresh: Option<V>) -> Option<Arc<V>> {
+        let key = self.key.take().expect("key is only taken here or on drop");
+        let result = self.cache.store(key, ttl, fresh);
+        self.flight.finish(result.clone());
+        result
+    }
+}
+
+impl<K: Clone + Eq + Hash, V> Drop for FlightGuard<K, V> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.cache.inner.lock_recover().in_flight.remove(&key);
+            self.flight.finish(None);
+        }
+    }
+}
+
+impl<K: Clone + Eq + Hash, V> KeyedCache<K, V> {
+    pub fn new(policy: CachePolicy, max_entries: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                entries: HashMap::new(),
+                in_flight: HashMap::new(),
+                clock: 0,
+            })),
+            policy,
+            max_entries: max_entries.max(1),
+            stats: Arc::default(),
+        }
+    }
+
+    pub fn summary(&self) -> CacheSummary {
+        CacheSummary {
+            hits: self.stats.hits.load(Ordering::Relaxed),
+            misses: self.stats.misses.load(Ordering::Relaxed),
+            stale: self.stats.stale.load(Ordering::Relaxed),
+            evictions: self.stats.evictions.load(Ordering::Relaxed),
+            refreshed_at: self.inner.lock_recover().entries.values().map(|entry| entry.refreshed_at).max(),
+        }
+    }
+
+    pub fn snapshot(&self) -> Vec<SavedEntry<K, V>> {
+        let (now, wall_now) = (Instant::now(), Utc::now());
+        self.inner.lock_recover().entries.iter()
+            .map(|(key, entry)| {
+                let expires_at = match entry.expires_at.checked_duration_since(now) {
+                    Some(left) => wall_now + left,
+                    None => wall_now - now.duration_since(entry.expires_at),
+                };
+                SavedEntry {
+                    key: key.clone(),
+                    value: Arc::clone(&entry.value),
+                    refreshed_at: entry.refreshed_at,
+                    expires_at,
+                }
+            })
+            .collect()
+    }
+
+    /// Puts saved entries back with the expiry they had, so entries that
+    /// went stale while the server was down get refreshed as usual.
+    pub fn restore(&self, saved: Vec<SavedEntry<K, V>>) {
+        let (now, wall_now) = (Instant::now(), Utc::now());
+        let mut inner = self.inner.lock_recover();
+
+        for entry in saved {
+            let expires_at = match (entry.expires_at - wall_now).to_std() {
+                Ok(left) => now + left,
+                // Negative, the entry expired this long ago.
+                Err(_) => (wall_now - entry.expires_at).to_std().ok()
+                    .and_then(|ago| now.checked_sub(ago))
+                    .unwrap_or(now),
+            };
+            inner.clock += 1;
+            let last_used = inner.clock;
+            inner.entries.insert(entry.key, Entry {
+                value: entry.value,
+                expires_at,
+                refreshed_at: entry.refreshed_at,
+                last_used,
+            });
+        }
+        self.evict_over_limit(&mut inner);
+    }
+
+    /// Saves a fetch result and ends its flight. A failed fetch keeps the
+    /// old value, if there still is one.
+    fn store(&self, key: K, ttl: Duration, fresh: Option<V>) -> Option<Arc<V>> {
+        let mut inner = self.inner.lock_recover();
+        inner.in_flight.remove(&key);
+
+        let Some(value) = fresh.map(Arc::new) else {
+            return inner.entries.get(&key).map(|entry| Arc::clone(&entry.value));
+        };
+
+        inner.clock += 1;
+        let entry = Entry {
+            value: Arc::clone(&value),
+            expires_at: Instant::now() + ttl,
+            refreshed_at: Utc::now(),
+            last_used: inner.clock,
+        };
+        if inner.entries.insert(key, entry).is_none() {
+            self.evict_over_limit(&mut inner);
+        }
+        Some(value)
+    }
+
+    fn evict_over_limit(&self, inner: &mut Inner<K, V>) {
+        while inner.entries.len() > self.max_entries {
+            let oldest = inner.entries.iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            let Some(oldest) = oldest else { break };
+            inner.entries.remove(&oldest);
+            self.stats.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl<K, V> KeyedCache<K, V>
+where
+    K: Clone + Eq + Hash + Send + 'static,
+    V: Send + Sync + 'static,
+{
+    pub fn get_or_update<F>(&self, key: K, fetcher: F) -> Option<Arc<V>>
+    where F: FnOnce() -> Option<V> + Send + 'static,
+    {
+        self.get_or_update_with_ttl(key, self.policy.ttl, fetcher)
+    }
+
+    /// Like `get_or_update`, but a freshly fetched value is kept for `ttl`
+    /// instead of the policy's.
+    pub fn get_or_update_with_ttl<F>(&self, key: K, ttl: Duration, fetcher: F) -> Option<Arc<V>>
+    where F: FnOnce() -> Option<V> + Send + 'static,
+    {
+        let mut inner = self.inner.lock_recover();
+        inner.clock += 1;
+        let tick = inner.clock;
+        let now = Instant::now();
+
+        if let Some(entry) = inner.entries.get_mut(&key) {
+            let refresh_at = entry.expires_at.checked_sub(self.policy.refresh_ahead).unwrap_or(entry.expires_at);
+            // A `max_stale` too large to add up means stale values never run out.
+            let usable = entry.expires_at.checked_add(self.policy.max_stale).is_none_or(|limit| now < limit);
+
+            if usable {
+                entry.last_used = tick;
+                let value = Arc::clone(&entry.value);
+                if now < entry.expires_at {
+                    self.stats.hits.fetch_add(1, Ordering::Relaxed);
+                } else {
+                    self.stats.stale.fetch_add(1, Ordering::Relaxed);
+                }
+
+                if now >= refresh_at && !inner.in_flight.contains_key(&key) {
+                    let guard = self.start_flight(&mut inner, key);
+                    drop(inner);
+                    refresh_in_background(guard, ttl, fetcher);
+                }
+                return Some(value);
+            }
+        }
+
+        self.stats.misses.fetch_add(1, Ordering::Relaxed);
+
+        if let Some(flight) = inner.in_flight.get(&key) {
+            let flight = Arc::clone(flight);
+            drop(inner);
+            return flight.wait();
+        }
+
+        let guard = self.start_flight(&mut inner, key);
+        drop(inner);
+        guard.finish(ttl, fetcher())
+    }
+
+    fn start_flight(&self, inner: &mut Inner<K, V>, key: K) -> FlightGuard<K, V> {
+        let flight = Arc::new(Flight { result: Mutex::new(None), done: Condvar::new() });
+        inner.in_flight.insert(key.clone(), Arc::clone(&flight));
+        FlightGuard { cache: self.clone(), key: Some(key), flight }
+    }
+}
+
+fn refresh_in_background<K, V, F>(guard: FlightGuard<K, V>, ttl: Duration, fetcher: F)
+where
+    K: Clone + Eq + Hash + Send + 'static,
+    V: Send + Sync + 'static,
+    F: FnOnce() -> Option<V> + Send + 'static,
+{
+    let spawned = thread::Builder::new()
+        .name("cache-refresh".into())
+        .spawn(move || { guard.finish(ttl, fetcher()); });
+
+    if let Err(e) = spawned {
+        eprintln!("ERROR: Couldn't start background cache refresh: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::{Arc, Barrier, atomic::{AtomicUsize, Ordering}}, thread, time::{Duration, Instant}};
+
+    use crate::util::{LockExt, cache::CachePolicy};
+
+    use super::KeyedCache;
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    fn cache<V>(max_entries: usize) -> KeyedCache<&'static str, V> {
+        KeyedCache::new(CachePolicy::new(TTL), max_entries)
+    }
+
+    fn len<K, V>(cache: &KeyedCache<K, V>) -> usize {
+        cache.inner.lock_recover().entries.len()
+    }
+
+    /// Waits for a background refresh to store `expected`.
+    fn eventually(cache: &KeyedCache<&'static str, u32>, key: &'static str, expected: u32) {
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while Instant::now() < deadline {
+            if cache.get_or_update(key, || None).as_deref() == Some(&expected) {
+                return;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        panic!("`{key}` never became {expected}");
+    }
+
+    #[test]
+    fn caches_per_key() {
+        let cache = cache(8);
+        let calls = Arc::new(AtomicUsize::new(0));
+        let fetch = |value: u32| {
+            let calls = Arc::clone(&calls);
+            move || { calls.fetch_add(1, Ordering::SeqCst); Some(value) }
+        };
+
+        assert_eq!(cache.get_or_update("a", fetch(1)).as_deref(), Some(&1));
+        assert_eq!(cache.get_or_update("b", fetch(2)).as_deref(), Some(&2));
+        assert_eq!(cache.get_or_update("a", fetch(3)).as_deref(), Some(&1));
+
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        let summary = cache.summary();
+        assert_eq!((summary.hits, summary.misses), (1, 2));
+        assert!(summary.refreshed_at.is_some());
+    }
+
+    #[test]
+    fn expired_entries_are_fetched_again() {
+        let cache = cache(8);
+        cache.get_or_update_with_ttl("a", Duration::ZERO, || Some(1));
+
+        assert_eq!(cache.get_or_update("a", || Some(2)).as_deref(), Some(&2));
+        assert_eq!(cache.get_or_update("a", || Some(3)).as_deref(), Some(&2));
+    }
+
+    #[test]
+    fn failed_fetch_keeps_expired_value() {
+        let cache = cache(8);
+        cache.get_or_update_with_ttl("a", Duration::ZERO, || Some(1));
+
+        assert_eq!(cache.get_or_update("a", || None).as_deref(), Some(&1));
+        assert_eq!(cache.get_or_update("b", || None::<u32>), None);
+        assert_eq!(len(&cache), 1);
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let cache = cache(2);
+        cache.get_or_update("a", || Some(1));
+        cache.get_or_update("b", || Some(2));
+        // Reading `a` makes `b` the least recently used.
+        cache.get_or_update("a", || Some(10));
+        cache.get_or_update("c", || Some(3));
+
+        assert_eq!(len(&cache), 2);
+        assert_eq!(cache.summary().evictions, 1);
+        assert_eq!(cache.get_or_update("a", || Some(10)).as_deref(), Some(&1));
+        assert_eq!(cache.get_or_update("c", || Some(30)).as_deref(), Some(&3));
+        assert_eq!(cache.get_or_update("b", || Some(20)).as_deref(), Some(&20));
+    }
+
+    #[test]
+    fn refreshing_an_entry_doesnt_evict() {
+        let cache = cache(2);
+        cache.get_or_update("a", || Some(1));
+        cache.get_or_update_with_ttl("b", Duration::ZERO, || Some(2));
+        cache.get_or_update("b", || Some(3));
+
+        assert_eq!(len(&cache), 2);
+        assert_eq!(cache.summary().evictions, 0);
+    }
+
+    #[test]
+    fn concurrent_misses_fetch_once() {
+        let cache = cache(8);
+        let calls = Arc::new(AtomicUsize::new(0));
+        let barrier = Arc::new(Barrier::new(8));
+
+        let handles: Vec<_> = (0..8).map(|_| {
+            let (cache, calls, barrier) = (cache.clone(), Arc::clone(&calls), Arc::clone(&barrier));
+            thread::spawn(move || {
+                barrier.wait();
+                cache.get_or_update("a", move || {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(50));
+                    Some(42)
+                })
+            })
+        }).collect();
+
+        for handle in handles {
+            assert_eq!(handle.join().unwrap().as_deref(), Some(&42));
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn different_keys_fetch_in_parallel() {
+        let cache = cache(8);
+        let barrier = Arc::new(Barrier::new(2));
+
+        // Each fetcher waits for the other one, which deadlocks if fetches
+        // for different keys were serialized.
+        let handles: Vec<_> = ["a", "b"].into_iter().map(|key| {
+            let (cache, barrier) = (cache.clone(), Arc::clone(&barrier));
+            thread::spawn(move || cache.get_or_update(key, move || { barrier.wait(); Some(key) }))
+        }).collect();
+
+        for handle in handles {
+            assert!(handle.join().unwrap().is_some());
+        }
+    }
+
+    #[test]
+    fn waiters_get_failed_result() {
+        let cache = cache::<u32>(8);
+        let calls = Arc::new(AtomicUsize::new(0));
+        let barrier = Arc::new(Barrier::new(4));
+
+        let handles: Vec<_> = (0..4).map(|_| {
+            let (cache, calls, barrier) = (cache.clone(), Arc::clone(&calls), Arc::clone(&barrier));
+            thread::spawn(move || {
+                barrier.wait();
+                cache.get_or_update("a", move || {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(50));
+                    None
+                })
+            })
+        }).collect();
+
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), None);
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn panicking_fetcher_releases_waiters() {
+        let cache = cache::<u32>(8);
+
+        let leader = {
+            let cache = cache.clone();
+            thread::spawn(move || cache.get_or_update("a", || {
+                thread::sleep(Duration::from_millis(50));
+                panic!("fetch failed");
+            }))
+        };
+        thread::sleep(Duration::from_millis(10));
+        let waiter = {
+            let cache = cache.clone();
+            thread::spawn(move || cache.get_or_update("a", || Some(1)))
+        };
+
+        assert!(leader.join().is_err());
+        // The waiter shares the failed fetch, or starts its own if it came
+        // in after the leader was gone.
+        let expected = match waiter.join().unwrap() {
+            Some(_) => 1,
+            None => 2,
+        };
+        assert_eq!(cache.get_or_update("a", || Some(2)).as_deref(), Some(&expected));
+    }
+
+    #[test]
+    fn serves_stale_value_while_refreshing() {
+        let policy = CachePolicy { max_stale: TTL, ..CachePolicy::new(TTL) };
+        let cache = KeyedCache::new(policy, 8);
+        cache.get_or_update_with_ttl("a", Duration::ZERO, || Some(1));
+
+        let started = Instant::now();
+        let value = cache.get_or_update("a", || { thread::sleep(Duration::from_millis(100)); Some(2) });
+        assert_eq!(value.as_deref(), Some(&1));
+        assert!(started.elapsed() < Duration::from_millis(100));
+
+        eventually(&cache, "a", 2);
+        assert!(cache.summary().stale >= 1);
+    }
+
+    #[test]
+    fn blocks_once_too_stale() {
+        let policy = CachePolicy { max_stale: Duration::from_millis(10), ..CachePolicy::new(TTL) };
+        let cache = KeyedCache::new(policy, 8);
+        cache.get_or_update_with_ttl("a", Duration::ZERO, || Some(1));
+        thread::sleep(Duration::from_millis(20));
+
+        assert_eq!(cache.get_or_update("a", || Some(2)).as_deref(), Some(&2));
+    }
+
+    #[test]
+    fn huge_max_stale_serves_stale_forever() {
+        let policy = CachePolicy { max_stale: Duration::MAX, ..CachePolicy::new(TTL) };
+        let cache = KeyedCache::new(policy, 8);
+        cache.get_or_update_with_ttl("a", Duration::ZERO, || Some(1));
+
+        assert_eq!(cache.get_or_update("a", || Some(2)).as_deref(), Some(&1));
+        eventually(&cache, "a", 2);
+    }
+
+    #[test]
+    fn refreshes_ahead_of_expiry() {
+        let policy = CachePolicy { refresh_ahead: TTL, ..CachePolicy::new(TTL) };
+        let cache = KeyedCache::new(policy, 8);
+        cache.get_or_update("a", || Some(1));
+
+        assert_eq!(cache.get_or_update("a", || Some(2)).as_deref(), Some(&1));
+        eventually(&cache, "a", 2);
+    }
+
+    #[test]
+    fn restores_snapshot_with_expiry() {
+        let saved = KeyedCache::new(CachePolicy::new(TTL), 8);
+        saved.get_or_update("fresh".to_string(), || Some(1));
+        saved.get_or_update_with_ttl("expired".to_string(), Duration::ZERO, || Some(2));
+        let json = serde_json::to_string(&saved.snapshot()).unwrap();
+
+        let restored = KeyedCache::new(CachePolicy::new(TTL), 8);
+        restored.restore(serde_json::from_str(&json).unwrap());
+
+        assert_eq!(restored.get_or_update("fresh".to_string(), || Some(10)).as_deref(), Some(&1));
+        assert_eq!(restored.get_or_update("expired".to_string(), || Some(20)).as_deref(), Some(&20));
+        assert!(restored.summary().refreshed_at.is_some());
+    }
+}
@@ -1,7 +1,11 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::{Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard}};
+
+use url::form_urlencoded;
 
 pub mod cache;
+pub mod keyed_cache;
 pub mod rate_limiter;
+pub mod reload;
 pub mod threadpool;
 
 pub fn parse_query(url: &str) -> HashMap<String, String> {
@@ -10,10 +14,86 @@ pub fn parse_query(url: &str) -> HashMap<String, String> {
         None => return HashMap::new()
     };
 
-    query_str.split("&")
-        .filter_map(|pair| {
-            let (key, value) = pair.split_once("=")?;
-            Some((key.to_string(), value.to_string()))
-        })
+    form_urlencoded::parse(query_str.as_bytes())
+        .into_owned()
         .collect()
 }
+
+/// Lowercase ASCII letters and digits with single dashes in between, for
+/// URLs and heading ids.
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    while slug.ends_with('-') { slug.pop(); }
+    slug
+}
+
+/// Locking that recovers from poisoning. A request that panicked while
+/// holding a lock shouldn't take every later request down with it, and
+/// none of the shared state here is left half-updated by a panic.
+pub trait LockExt<T> {
+    fn lock_recover(&self) -> MutexGuard<'_, T>;
+}
+
+impl<T> LockExt<T> for Mutex<T> {
+    fn lock_recover(&self) -> MutexGuard<'_, T> {
+        self.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+pub trait RwLockExt<T> {
+    fn read_recover(&self) -> RwLockReadGuard<'_, T>;
+    fn write_recover(&self) -> RwLockWriteGuard<'_, T>;
+}
+
+impl<T> RwLockExt<T> for RwLock<T> {
+    fn read_recover(&self) -> RwLockReadGuard<'_, T> {
+        self.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write_recover(&self) -> RwLockWriteGuard<'_, T> {
+        self.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::{Arc, Mutex, RwLock}, thread};
+
+    use super::{LockExt, RwLockExt, slugify};
+
+    #[test]
+    fn poisoned_locks_are_recovered() {
+        let mutex = Arc::new(Mutex::new(1));
+        let rwlock = Arc::new(RwLock::new(1));
+
+        let (m, r) = (Arc::clone(&mutex), Arc::clone(&rwlock));
+        let result = thread::spawn(move || {
+            let _m = m.lock().unwrap();
+            let _r = r.write().unwrap();
+            panic!("poison both locks");
+        }).join();
+
+        assert!(result.is_err());
+        assert!(mutex.is_poisoned() && rwlock.is_poisoned());
+
+        *mutex.lock_recover() += 1;
+        *rwlock.write_recover() += 1;
+        assert_eq!(*mutex.lock_recover(), 2);
+        assert_eq!(*rwlock.read_recover(), 2);
+    }
+
+    #[test]
+    fn slugifies_to_single_dashes() {
+        assert_eq!(slugify("Hello, World!"), "hello-world");
+        assert_eq!(slugify("  --Rust & C++ -- 2024  "), "rust-c-2024");
+        assert_eq!(slugify("Café au lait"), "caf-au-lait");
+        assert_eq!(slugify("???"), "");
+    }
+}
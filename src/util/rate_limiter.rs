@@ -18,25 +18,32 @@ pub fn get_client_ip(request: &Request) -> Option<IpAddr> {
 
 pub struct RateLimiter {
     last_request: HashMap<IpAddr, Instant>,
-    cooldown: Duration
+    cooldown: Duration,
+    rejections: u64,
 }
 
 impl RateLimiter {
     pub fn new(cooldown: Duration) -> Self {
         RateLimiter {
             last_request: HashMap::new(),
-            cooldown
+            cooldown,
+            rejections: 0,
         }
     }
 
+    pub fn rejections(&self) -> u64 {
+        self.rejections
+    }
+
     pub fn is_allowed(&mut self, ip: IpAddr) -> bool {
         let now = Instant::now();
 
         match self.last_request.get(&ip) {
             Some(&last) => {
-                let is_allowed = !(now.duration_since(last) < self.cooldown);
+                let is_allowed = now.duration_since(last) >= self.cooldown;
                 self.last_request.insert(ip, now);
-                return is_allowed;
+                if !is_allowed { self.rejections += 1; }
+                is_allowed
             }
             _ => {
                 self.last_request.insert(ip, now);
@@ -0,0 +1,156 @@
+use std::{sync::{Arc, RwLock}, time::{Duration, Instant, SystemTime}};
+
+use crate::{assets, util::RwLockExt};
+
+/// How often the file's modification time is looked at, so busy pages don't
+/// stat the file on every request.
+const CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+struct ReloadState<T> {
+    value: Arc<T>,
+    modified: Option<SystemTime>,
+    checked: Instant,
+}
+
+type Modified = Box<dyn Fn() -> Option<SystemTime> + Send + Sync>;
+type Load<T> = Box<dyn Fn() -> Result<T, ()> + Send + Sync>;
+
+/// A value loaded from disk that is reloaded when its modification time
+/// changes. A source that fails to load or parse keeps the previous version
+/// around.
+pub struct Reloadable<T> {
+    name: String,
+    modified: Modified,
+    load: Load<T>,
+    state: RwLock<ReloadState<T>>,
+}
+
+impl<T: 'static> Reloadable<T> {
+    /// Parses a file under the static root. The first load has to succeed.
+    pub fn new(rel_path: &'static str, parse: fn(&str) -> Result<T, ()>) -> Result<Self, ()> {
+        let (modified, load) = Self::file_source(rel_path, parse);
+        Self::with_loader(rel_path.to_string(), modified, load)
+    }
+
+    /// Like `new`, but starts out with `fallback` when the first load fails.
+    /// The file is still watched, so it shows up once it's fixed.
+    pub fn with_fallback(rel_path: &'static str, parse: fn(&str) -> Result<T, ()>, fallback: T) -> Self {
+        let (modified, load) = Self::file_source(rel_path, parse);
+        let stamp = modified();
+        let value = load().unwrap_or_else(|()| {
+            eprintln!("ERROR: Using a placeholder for `{rel_path}` until it loads");
+            fallback
+        });
+
+        Self::from_parts(rel_path.to_string(), modified, load, stamp, value)
+    }
+
+    /// Reloads through `load` whenever `modified` returns something else than
+    /// it did before. The first load has to succeed.
+    pub fn with_loader(name: String, modified: Modified, load: Load<T>) -> Result<Self, ()> {
+        let stamp = modified();
+        let value = load()
+            .map_err(|_| eprintln!("ERROR: Couldn't load `{name}`"))?;
+
+        Ok(Self::from_parts(name, modified, load, stamp, value))
+    }
+
+    fn file_source(rel_path: &'static str, parse: fn(&str) -> Result<T, ()>) -> (Modified, Load<T>) {
+        (
+            Box::new(move || assets::modified(rel_path)),
+            Box::new(move || {
+                let content = assets::read_text(rel_path)
+                    .ok_or_else(|| eprintln!("ERROR: Couldn't load `{rel_path}`"))?;
+                parse(&content)
+            }),
+        )
+    }
+
+    fn from_parts(name: String, modified: Modified, load: Load<T>, stamp: Option<SystemTime>, value: T) -> Self {
+        Self {
+            name,
+            modified,
+            load,
+            state: RwLock::new(ReloadState { value: Arc::new(value), modified: stamp, checked: Instant::now() }),
+        }
+    }
+
+    /// Returns the current version, reloading it first if the file changed.
+    pub fn get(&self) -> Arc<T> {
+        {
+            let state = self.state.read_recover();
+            if state.checked.elapsed() < CHECK_INTERVAL {
+                return Arc::clone(&state.value);
+            }
+        }
+
+        let mut state = self.state.write_recover();
+        // Another request may have checked while this one waited for the lock.
+        if state.checked.elapsed() >= CHECK_INTERVAL {
+            state.checked = Instant::now();
+
+            let modified = (self.modified)();
+            if modified != state.modified {
+                // Remember the new time even on failure, so a broken file is
+                // only reported once instead of on every check.
+                state.modified = modified;
+                match (self.load)() {
+                    Ok(value) => {
+                        state.value = Arc::new(value);
+                        println!("Reloaded `{}`", self.name);
+                    }
+                    Err(()) => eprintln!("ERROR: Keeping the previous version of `{}`", self.name),
+                }
+            }
+        }
+
+        Arc::clone(&state.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::{Arc, Mutex}, time::{Duration, SystemTime}};
+
+    use super::{CHECK_INTERVAL, Reloadable};
+
+    /// A source whose modification time and content the test controls.
+    type Source = Arc<Mutex<(u64, &'static str)>>;
+
+    fn reloadable(source: &Source) -> Reloadable<u32> {
+        let (modified, load) = (Arc::clone(source), Arc::clone(source));
+        Reloadable::with_loader(
+            "number".into(),
+            Box::new(move || Some(SystemTime::UNIX_EPOCH + Duration::from_secs(modified.lock().unwrap().0))),
+            Box::new(move || load.lock().unwrap().1.parse().map_err(|_| ())),
+        ).unwrap()
+    }
+
+    fn change(reloadable: &Reloadable<u32>, source: &Source, content: &'static str) {
+        let mut source = source.lock().unwrap();
+        *source = (source.0 + 1, content);
+        reloadable.state.write().unwrap().checked -= CHECK_INTERVAL;
+    }
+
+    #[test]
+    fn reloads_changed_source() {
+        let source: Source = Arc::new(Mutex::new((0, "1")));
+        let number = reloadable(&source);
+        assert_eq!(*number.get(), 1);
+
+        change(&number, &source, "2");
+        assert_eq!(*number.get(), 2);
+    }
+
+    #[test]
+    fn keeps_previous_version_on_parse_error() {
+        let source: Source = Arc::new(Mutex::new((0, "1")));
+        let number = reloadable(&source);
+
+        change(&number, &source, "not a number");
+        assert_eq!(*number.get(), 1);
+
+        change(&number, &source, "3");
+        assert_eq!(*number.get(), 3);
+    }
+}
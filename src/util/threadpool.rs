@@ -1,23 +1,48 @@
-use std::{thread, sync::{Arc, Mutex, mpsc}};
+use std::{thread, panic::{self, AssertUnwindSafe}, sync::{Arc, Mutex, PoisonError, mpsc, atomic::{AtomicUsize, Ordering}}, time::{Duration, Instant}};
+
+use crate::util::LockExt;
 
 type Job = Box<dyn FnOnce() + Send + 'static>;
 
+pub struct PoolStats {
+    size: usize,
+    capacity: usize,
+    queued: AtomicUsize,
+    busy: AtomicUsize,
+    respawned: AtomicUsize,
+    shed: AtomicUsize,
+}
+
+impl PoolStats {
+    pub fn size(&self) -> usize { self.size }
+    pub fn capacity(&self) -> usize { self.capacity }
+    pub fn queued(&self) -> usize { self.queued.load(Ordering::Relaxed) }
+    pub fn busy(&self) -> usize { self.busy.load(Ordering::Relaxed) }
+    pub fn respawned(&self) -> usize { self.respawned.load(Ordering::Relaxed) }
+    pub fn shed(&self) -> usize { self.shed.load(Ordering::Relaxed) }
+}
+
 struct Worker {
     id: usize,
     thread: Option<thread::JoinHandle<()>>,
 }
 
 impl Worker {
-    pub fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Job>>>) -> Worker {
+    pub fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Job>>>, stats: Arc<PoolStats>) -> Worker {
         let thread = thread::spawn(move || loop {
             let msg = {
-                let lock = receiver.lock().unwrap();
+                let lock = receiver.lock_recover();
                 lock.recv()
             };
 
             match msg {
                 Ok(job) => {
-                    job();
+                    stats.queued.fetch_sub(1, Ordering::Relaxed);
+                    stats.busy.fetch_add(1, Ordering::Relaxed);
+                    if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+                        eprintln!("ERROR: Worker {id} recovered from a panicking job");
+                    }
+                    stats.busy.fetch_sub(1, Ordering::Relaxed);
                 },
                 Err(_) => {
                     break;
@@ -27,42 +52,277 @@ impl Worker {
 
         Worker { id, thread: Some(thread) }
     }
+
+    fn is_dead(&self) -> bool {
+        self.thread.as_ref().is_some_and(|t| t.is_finished())
+    }
 }
 
 pub struct ThreadPool {
-    workers: Vec<Worker>,
-    sender: mpsc::Sender<Job>
+    workers: Mutex<Vec<Worker>>,
+    receiver: Arc<Mutex<mpsc::Receiver<Job>>>,
+    sender: Option<mpsc::SyncSender<Job>>,
+    stats: Arc<PoolStats>,
 }
 
 impl ThreadPool {
-    pub fn new(size: usize) -> Self {
+    /// Creates `size` workers sharing a queue that holds at most `capacity`
+    /// jobs that haven't been picked up yet.
+    pub fn new(size: usize, capacity: usize) -> Self {
         assert!(size > 0);
+        assert!(capacity > 0);
 
-        let (sender, receiver) = mpsc::channel();
+        let (sender, receiver) = mpsc::sync_channel(capacity);
         let receiver = Arc::new(Mutex::new(receiver));
+        let stats = Arc::new(PoolStats {
+            size,
+            capacity,
+            queued: AtomicUsize::new(0),
+            busy: AtomicUsize::new(0),
+            respawned: AtomicUsize::new(0),
+            shed: AtomicUsize::new(0),
+        });
 
         let workers = (0..size)
-            .map(|id| Worker::new(id, Arc::clone(&receiver)))
+            .map(|id| Worker::new(id, Arc::clone(&receiver), Arc::clone(&stats)))
             .collect();
 
-        ThreadPool { workers, sender }
+        ThreadPool { workers: Mutex::new(workers), receiver, sender: Some(sender), stats }
     }
 
-    pub fn execute<F>(&self, f: F)
-    where F: FnOnce() + Send + 'static
+    /// Jobs can't kill a worker since their panics are caught, but if a
+    /// thread dies anyway it gets replaced before the next job is queued.
+    fn respawn_dead_workers(&self) {
+        let mut workers = self.workers.lock_recover();
+        for worker in workers.iter_mut().filter(|w| w.is_dead()) {
+            eprintln!("ERROR: Worker {} died, respawning it", worker.id);
+            if let Some(thread) = worker.thread.take() { let _ = thread.join(); }
+            *worker = Worker::new(worker.id, Arc::clone(&self.receiver), Arc::clone(&self.stats));
+            self.stats.respawned.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn stats(&self) -> Arc<PoolStats> {
+        Arc::clone(&self.stats)
+    }
+
+    /// Queues `f(input)` unless the queue is full. Then nothing runs, the job
+    /// counts as shed and `input` comes back so the caller can still answer
+    /// it some other way.
+    pub fn try_execute<T, F>(&self, input: T, f: F) -> Result<(), T>
+    where
+        T: Send + 'static,
+        F: FnOnce(T) + Send + 'static,
     {
-        let job = Box::new(f);
-        self.sender.send(job).expect("ThreadPool queue disconnected");
+        self.respawn_dead_workers();
+
+        // A rejected job can't be taken apart again, so the input waits in
+        // a slot that's emptied by whoever gets to it.
+        let slot = Arc::new(Mutex::new(Some(input)));
+        let job_slot = Arc::clone(&slot);
+        let job: Job = Box::new(move || {
+            let input = job_slot.lock_recover().take();
+            if let Some(input) = input { f(input) }
+        });
+
+        self.stats.queued.fetch_add(1, Ordering::Relaxed);
+        let sent = self.sender.as_ref()
+            .expect("ThreadPool already shut down")
+            .try_send(job);
+
+        match sent {
+            Ok(()) => Ok(()),
+            Err(mpsc::TrySendError::Full(_)) => {
+                self.stats.queued.fetch_sub(1, Ordering::Relaxed);
+                self.stats.shed.fetch_add(1, Ordering::Relaxed);
+                Err(slot.lock_recover().take().expect("a rejected job never ran"))
+            }
+            Err(mpsc::TrySendError::Disconnected(_)) => panic!("ThreadPool queue disconnected"),
+        }
+    }
+
+    /// Closes the queue and waits for the workers to finish every job that
+    /// was already submitted. Returns `false` if some were still running when
+    /// `timeout` ran out; those threads are left detached.
+    pub fn shutdown(mut self, timeout: Duration) -> bool {
+        drop(self.sender.take());
+
+        let workers = self.workers.get_mut().unwrap_or_else(PoisonError::into_inner);
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            let all_finished = workers.iter()
+                .filter_map(|w| w.thread.as_ref())
+                .all(|t| t.is_finished());
+
+            if all_finished { break }
+            if Instant::now() >= deadline {
+                for worker in workers.iter_mut() { worker.thread.take(); }
+                return false
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        for worker in workers.iter_mut() {
+            if let Some(thread) = worker.thread.take() {
+                let _ = thread.join();
+            }
+        }
+        true
     }
 }
 
 impl Drop for ThreadPool {
     fn drop(&mut self) {
-        drop(self.sender.clone());
-        for worker in &mut self.workers {
+        drop(self.sender.take());
+        let workers = self.workers.get_mut().unwrap_or_else(PoisonError::into_inner);
+        for worker in workers.iter_mut() {
             if let Some(thread) = worker.thread.take() {
-                thread.join().unwrap();
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, sync::{Arc, Mutex, atomic::{AtomicUsize, Ordering}, mpsc}, time::Duration};
+
+    use crate::util::LockExt;
+
+    use super::ThreadPool;
+
+    fn execute(pool: &ThreadPool, f: impl FnOnce() + Send + 'static) {
+        assert!(pool.try_execute((), move |()| f()).is_ok(), "queue is full");
+    }
+
+    #[test]
+    fn shutdown_finishes_queued_jobs() {
+        let pool = ThreadPool::new(2, 16);
+        let done = Arc::new(AtomicUsize::new(0));
+
+        for _ in 0..10 {
+            let done = Arc::clone(&done);
+            execute(&pool, move || {
+                thread::sleep(Duration::from_millis(10));
+                done.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+
+        assert!(pool.shutdown(Duration::from_secs(5)));
+        assert_eq!(done.load(Ordering::SeqCst), 10);
+    }
+
+    #[test]
+    fn shutdown_gives_up_after_deadline() {
+        let pool = ThreadPool::new(1, 16);
+        let (release, blocked) = mpsc::channel::<()>();
+
+        execute(&pool, move || { let _ = blocked.recv(); });
+
+        assert!(!pool.shutdown(Duration::from_millis(50)));
+        release.send(()).unwrap();
+    }
+
+    #[test]
+    fn panicking_jobs_dont_kill_workers() {
+        let pool = ThreadPool::new(1, 16);
+        let stats = pool.stats();
+        let done = Arc::new(AtomicUsize::new(0));
+
+        for i in 0..6 {
+            let done = Arc::clone(&done);
+            execute(&pool, move || {
+                if i % 2 == 0 { panic!("job {i} panicked on purpose"); }
+                done.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+
+        assert!(pool.shutdown(Duration::from_secs(5)));
+        assert_eq!(done.load(Ordering::SeqCst), 3);
+        assert_eq!(stats.busy(), 0);
+        assert_eq!(stats.queued(), 0);
+        assert_eq!(stats.respawned(), 0);
+    }
+
+    #[test]
+    fn panic_while_holding_lock_doesnt_cascade() {
+        let pool = ThreadPool::new(2, 16);
+        let shared = Arc::new(Mutex::new(Vec::new()));
+
+        let poisoner = Arc::clone(&shared);
+        execute(&pool, move || {
+            let _guard = poisoner.lock().unwrap();
+            panic!("panic while holding the lock");
+        });
+
+        for i in 0..4 {
+            let shared = Arc::clone(&shared);
+            execute(&pool, move || {
+                thread::sleep(Duration::from_millis(5));
+                shared.lock_recover().push(i);
+            });
+        }
+
+        assert!(pool.shutdown(Duration::from_secs(5)));
+        assert_eq!(shared.lock_recover().len(), 4);
+    }
+
+    #[test]
+    fn dead_workers_are_respawned() {
+        let pool = ThreadPool::new(1, 16);
+        let done = Arc::new(AtomicUsize::new(0));
+
+        // Simulate a worker dying outside of a job. The original thread keeps
+        // running untracked until the queue closes.
+        let original = pool.workers.lock_recover()[0].thread.replace(thread::spawn(|| {}));
+        while !pool.workers.lock_recover()[0].is_dead() { thread::yield_now(); }
+
+        let counter = Arc::clone(&done);
+        execute(&pool, move || { counter.fetch_add(1, Ordering::SeqCst); });
+
+        assert_eq!(pool.stats().respawned(), 1);
+        assert!(pool.shutdown(Duration::from_secs(5)));
+        original.unwrap().join().unwrap();
+        assert_eq!(done.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn try_execute_sheds_when_full() {
+        let pool = ThreadPool::new(1, 2);
+        let (release, blocked) = mpsc::channel::<()>();
+        let (started, running) = mpsc::channel::<()>();
+        let done = Arc::new(AtomicUsize::new(0));
+
+        execute(&pool, move || {
+            started.send(()).unwrap();
+            let _ = blocked.recv();
+        });
+        running.recv().unwrap();
+
+        for i in 0..3 {
+            let done = Arc::clone(&done);
+            let queued = pool.try_execute(i, move |_| { done.fetch_add(1, Ordering::SeqCst); });
+            assert_eq!(queued, if i < 2 { Ok(()) } else { Err(2) });
+        }
+        assert_eq!(pool.stats().shed(), 1);
+        assert_eq!(pool.stats().queued(), 2);
+
+        release.send(()).unwrap();
+        assert!(pool.shutdown(Duration::from_secs(5)));
+        assert_eq!(done.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn drop_closes_queue_and_joins_workers() {
+        let done = Arc::new(AtomicUsize::new(0));
+        {
+            let pool = ThreadPool::new(4, 16);
+            for _ in 0..8 {
+                let done = Arc::clone(&done);
+                execute(&pool, move || { done.fetch_add(1, Ordering::SeqCst); });
             }
         }
+        assert_eq!(done.load(Ordering::SeqCst), 8);
     }
 }